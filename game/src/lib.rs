@@ -1,18 +1,26 @@
 //! Game logic for Handmade Ferris
 
-#![feature(const_fn_floating_point_arithmetic)]
 #![feature(stmt_expr_attributes)]
+#![feature(variant_count)]
 
+use core::mem::variant_count;
+use std::any::Any;
 use std::ops::Neg;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Mutex, Once};
 
-use game_state::{BitmapAsset, Button, Memory, Meters, TILE_MAP_COLUMNS, TILE_MAP_ROWS, MILLISECONDS_PER_FRAME, MEMORY_BASE_ADDR};
-use game_state::{ChunkVector, Error, Game, Result, Rng, State};
+use game_state::{BitmapAsset, Button, Entity, Memory, Meters, TILE_MAP_COLUMNS, TILE_MAP_ROWS, TARGET_FRAMES_PER_SECOND};
+use game_state::{ChunkVector, Error, Game, GameAbi, Result, Rng, SoundBuffer, SoundEffect, State};
 use game_state::{Color, PlayerDirection, Truncate};
 use game_state::{TILE_HALF_HEIGHT, TILE_HALF_WIDTH, TILE_HEIGHT, TILE_WIDTH};
-use game_state::Allocation;
+use game_state::{root_layout, Allocation, Migration, RootStatus, MAX_CONTROLLERS};
+use game_state::{profile, timed_block};
 
 use vector::Vector2;
 
+mod mixer;
+use mixer::Mixer;
+
 /// Type of tiles that inhabit the world
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, Default)]
@@ -168,8 +176,15 @@ pub struct World<const WIDTH: usize, const HEIGHT: usize> {
     /// Index to the next tile_map slot
     next_tile_map_index: usize,
 
-    /// Number of meters to step per frame (time delta)
-    pub delta_t: Meters,
+    /// Sounds currently playing
+    pub mixer: Mixer,
+
+    /// Real time (in seconds) not yet simulated, carried over to the next frame
+    pub accumulator: f32,
+
+    /// Button transitions of each controller since the last simulation step, so a tap
+    /// during a frame that runs no step is seen by the next step
+    pub pending_transitions: [[u8; variant_count::<Button>()]; MAX_CONTROLLERS],
 }
 
 impl<const WIDTH: usize, const HEIGHT: usize> World<WIDTH, HEIGHT> {
@@ -178,7 +193,9 @@ impl<const WIDTH: usize, const HEIGHT: usize> World<WIDTH, HEIGHT> {
         self.tile_maps = [Allocation::default(); PREALLOC_TILE_MAPS];
         self.tile_map_indexes = [None; PREALLOC_TILE_MAPS];
         self.next_tile_map_index = 0;
-        self.delta_t = Meters::new(MILLISECONDS_PER_FRAME / 1000.);
+        self.mixer = Mixer::default();
+        self.accumulator = 0.;
+        self.pending_transitions = [[0; variant_count::<Button>()]; MAX_CONTROLLERS];
     }
 
     /// Allocate a new [`TileMap`] at chunk id (`x`, `y`)
@@ -226,7 +243,7 @@ impl<const WIDTH: usize, const HEIGHT: usize> World<WIDTH, HEIGHT> {
         state.set_camera();
 
         let ChunkVector { chunk_id, offset: _ } = state.camera.into_chunk();
-        let tile_map = self.get_tilemap_at(chunk_id, state.camera.z, &mut game.memory, &mut state.rng);
+        let tile_map = self.get_tilemap_at(chunk_id, state.camera.z, game.memory, state.seed);
         tile_map.draw(game)
     }
 
@@ -241,7 +258,7 @@ impl<const WIDTH: usize, const HEIGHT: usize> World<WIDTH, HEIGHT> {
         pos: Vector2<u32>,
         z: u8,
         memory: &mut Memory,
-        rng: &mut Rng,
+        seed: u64,
     ) -> &mut TileMap<WIDTH, HEIGHT> {
         // Look for the requested (x, y) in the allocated tile maps and return the
         // pointer if found
@@ -257,25 +274,36 @@ impl<const WIDTH: usize, const HEIGHT: usize> World<WIDTH, HEIGHT> {
         }
 
         // Allocate and initialize a new tile map
-        self.init_tile_map(pos, z, memory, rng)
+        self.init_tile_map(pos, z, memory, seed)
+    }
+
+    /// Get the [`Rng`] used to generate the room at `chunk` on floor `z` of the world
+    /// generated from `seed`
+    fn room_rng(seed: u64, chunk: Vector2<u32>, z: u8) -> Rng {
+        let chunk = u64::from(chunk.x) << 32 | u64::from(chunk.y);
+
+        Rng::from_seed(
+            seed ^ chunk.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+                ^ u64::from(z).wrapping_mul(0xbf58_476d_1ce4_e5b9),
+        )
     }
 
-    /// Randomly initialize a tile map
+    /// Generate the room at `chunk` on floor `z` using only the world `seed`, returning
+    /// the room and the position of its ladder, if one was placed.
+    ///
+    /// Each room is derived only from the seed and its own coordinates, so a room is
+    /// always the same regardless of the order the rooms are visited.
     #[allow(clippy::cast_possible_truncation)]
-    fn init_tile_map(
-        &mut self,
+    fn generate_room(
+        seed: u64,
         chunk: Vector2<u32>,
         z: u8,
-        memory: &mut Memory,
-        rng: &mut Rng,
-    ) -> &mut TileMap<WIDTH, HEIGHT> {
-        // If a ladder is drawn, write the ladder in the adjacent floor location
-        let mut other_floor = None;
+    ) -> (TileMap<WIDTH, HEIGHT>, Option<(u16, u16)>) {
+        let mut rng = Self::room_rng(seed, chunk, z);
+        let mut tile_map = TileMap::default();
 
-        // No tilemap was found, allocate a new one
-        let tile_map = self.alloc_tilemap_at(memory, chunk, z);
-
-        let mut ladder_set = false;
+        // The ladder placed in this room, if any
+        let mut ladder = None;
 
         for y in 0..TILE_MAP_ROWS {
             for x in 0..TILE_MAP_COLUMNS {
@@ -297,76 +325,212 @@ impl<const WIDTH: usize, const HEIGHT: usize> World<WIDTH, HEIGHT> {
                         tile_map.set_tile_at(x as u16, y as u16, TileType::Wall);
                     }
                 }
-                // Randomly set values in a room
-                else if !ladder_set && rng.next() % 64 == 0 {
+                // Randomly set values in a room, only generating one ladder per floor
+                else if ladder.is_none() && rng.next().is_multiple_of(64) {
                     tile_map.set_tile_at(x as u16, y as u16, TileType::Ladder);
-
-                    // Set that we need to set the ladder position in the adjacent floor
-                    other_floor = Some((x as u16, y as u16));
-
-                    // Only generate one ladder per floor
-                    ladder_set = true;
-
-                    continue;
+                    ladder = Some((x as u16, y as u16));
                 }
                 // Randomly set values in a room
-                else if rng.next() % 16 == 0 {
+                else if rng.next().is_multiple_of(16) {
                     tile_map.set_tile_at(x as u16, y as u16, TileType::Wall);
                 }
             }
         }
 
-        // Get the same corresponding ladder on the other floor
-        if let Some((x, y)) = other_floor {
-            let other_z = (z + 1) % 2;
-            let other_tilemap = self.get_tilemap_at(chunk, other_z, memory, rng);
-            other_tilemap.set_tile_at(x, y, TileType::Ladder);
+        (tile_map, ladder)
+    }
+
+    /// Initialize the tile map for the room at `chunk` on floor `z`
+    fn init_tile_map(
+        &mut self,
+        chunk: Vector2<u32>,
+        z: u8,
+        memory: &mut Memory,
+        seed: u64,
+    ) -> &mut TileMap<WIDTH, HEIGHT> {
+        let (room, _) = Self::generate_room(seed, chunk, z);
+
+        // A ladder on the adjacent floor also lands in this room. Regenerate the adjacent
+        // floor to find it rather than depending on it already being visited.
+        let other_z = (z + 1) % 2;
+        let (_, other_ladder) = Self::generate_room(seed, chunk, other_z);
+
+        // No tilemap was found, allocate a new one
+        let tile_map = self.alloc_tilemap_at(memory, chunk, z);
+        *tile_map = room;
+
+        if let Some((x, y)) = other_ladder {
+            tile_map.set_tile_at(x, y, TileType::Ladder);
         }
 
-        self.get_tilemap_at(chunk, z, memory, rng)
+        tile_map
     }
 }
 
-/// Update and render the current game state
-///
-/// # Panics
-///
-/// * On 16 bit machines
-#[no_mangle]
-pub extern "C" fn game_update_and_render(game: &mut Game, state: &mut State) {
-    // Initialize the game memory if not already initialized
-    if !game.memory.initialized {
-        let mut world = game.memory.alloc::<World<TILE_MAP_COLUMNS, TILE_MAP_ROWS>>();
+/// The world used by the game, stored as the root structure of the game memory
+type GameWorld = World<TILE_MAP_COLUMNS, TILE_MAP_ROWS>;
 
-        // Initialize the world
-        world.init();
+/// Version of the [`GameWorld`] layout. Bump this when the fields of [`World`] or
+/// [`TileMap`] change without changing their sizes.
+const WORLD_LAYOUT_VERSION: u32 = 1;
 
-        // Game world is now initialized
-        game.memory.initialized = true;
+/// Layout id of the [`GameWorld`] in the game memory
+const WORLD_LAYOUT: u64 = root_layout::<GameWorld>(
+    WORLD_LAYOUT_VERSION,
+    &[std::mem::size_of::<TileMap<TILE_MAP_COLUMNS, TILE_MAP_ROWS>>()],
+);
+
+/// Migrations from older [`GameWorld`] layouts to [`WORLD_LAYOUT`]. A world with a
+/// layout that can't be migrated is reinitialized, keeping the [`State`].
+const WORLD_MIGRATIONS: &[Migration] = &[];
+
+/// The interface this library was built against, checked by the platform before any of
+/// the game functions are used
+#[no_mangle]
+pub static GAME_ABI: GameAbi = GameAbi::CURRENT;
+
+/// Location of the last panic in the game logic, recorded by the panic hook since the
+/// unwind payload only holds the message
+static PANIC_LOCATION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Replaces the default panic hook of this library so panics are reported to the
+/// platform instead of printed
+static PANIC_HOOK: Once = Once::new();
+
+/// Get the message from a panic payload
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
     }
+}
 
-    //
-    let res = _game_update_and_render(game, state);
+/// Run the game logic `func` with the world from the game memory
+///
+/// Panics in the game logic are caught here rather than unwinding into the platform and
+/// are reported through `game.error` as [`Error::Panic`].
+fn with_world(game: &mut Game, func: impl FnOnce(&mut Game, &mut GameWorld) -> Result<()>) {
+    // Record timed blocks into the platform's profiler. The library has its own copy of
+    // the profiler state, which is lost on every reload.
+    profile::set_profiler(Some(game.profiler));
+
+    PANIC_HOOK.call_once(|| {
+        std::panic::set_hook(Box::new(|info| {
+            if let Ok(mut location) = PANIC_LOCATION.lock() {
+                *location = info.location().map(ToString::to_string);
+            }
+        }));
+    });
+
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        // Get the world from the game memory, migrating or reinitializing it if it was
+        // left by a library with a different world layout
+        let (mut world, status) = game.memory.root::<GameWorld>(WORLD_LAYOUT, WORLD_MIGRATIONS);
+
+        if let RootStatus::Reinitialized { from } = status {
+            println!(
+                "World layout changed from {from:#x} to {WORLD_LAYOUT:#x} with no migration, \
+                 reinitializing the world"
+            );
+        }
+
+        // Initialize the world if it is new
+        if status.needs_init() {
+            world.init();
+        }
+
+        func(game, &mut world)
+    }));
 
     // Update the error code between the game logic library and the platform layer
-    game.error = res;
+    game.error = res.unwrap_or_else(|payload| {
+        let location = PANIC_LOCATION.lock().ok().and_then(|mut location| location.take());
+
+        Err(Error::Panic {
+            message: panic_message(payload.as_ref()),
+            location: location.unwrap_or_else(|| "<unknown>".to_string()),
+        })
+    });
+}
+
+/// Update and render the current game state
+#[no_mangle]
+pub extern "C" fn game_update_and_render(game: &mut Game, state: &mut State) {
+    with_world(game, |game, world| _game_update_and_render(game, state, world));
+}
+
+/// Fill the `sound` buffer with the samples following the last buffer
+#[no_mangle]
+pub extern "C" fn game_get_sound_samples(game: &mut Game, state: &mut State, sound: &mut SoundBuffer) {
+    with_world(game, |game, world| _game_get_sound_samples(game, state, world, sound));
+}
+
+/// Mix the sounds playing in the world, heard from the camera
+fn _game_get_sound_samples(game: &Game, state: &State, world: &mut GameWorld, sound: &mut SoundBuffer) -> Result<()> {
+    timed_block!("mix");
+
+    world.mixer.mix(&state.camera, &game.sound_assets, sound);
+    Ok(())
 }
 
+/// Seconds of game time simulated by each fixed simulation step
+const SECONDS_PER_STEP: f32 = 1.0 / TARGET_FRAMES_PER_SECOND;
+
+/// Most simulation steps run in one frame. Longer stalls, such as sitting on a
+/// breakpoint, slow the simulation down rather than running a burst of steps to catch up.
+const MAX_STEPS_PER_FRAME: f32 = 4.0;
+
+/// Distance a player moves between footsteps
+const FOOTSTEP_STRIDE: Meters = Meters::const_new(2.0);
+
+/// Speed into a wall (in meters per second) needed to hear the player bump into it
+const BUMP_MIN_SPEED: f32 = 2.0;
+
+/// Speed into a wall (in meters per second) where the bump is loudest
+const BUMP_FULL_SPEED: f32 = 18.0;
+
 /// Actual game logic code that can return a [`Result`]
-fn _game_update_and_render(game: &mut Game, state: &mut State) -> Result<()> {
-    // Draw the background
-    game.background.draw(game, Vector2::new(0., 0.));
+fn _game_update_and_render(game: &mut Game, state: &mut State, world: &mut GameWorld) -> Result<()> {
+    // Pressing start on a controller without a player joins the game as a new player.
+    // Checked every frame rather than every step so a press is never missed.
+    for (controller_index, controller) in game.controllers.iter().enumerate() {
+        if controller.is_connected
+                && controller.button(Button::Start).was_pressed()
+                && state.controller_entities[controller_index].is_none() {
+            let entity_index = state.add_player(controller_index);
+            println!("Controller {controller_index} joined as entity {entity_index}");
+        }
+    }
 
+    // Hold on to this frame's button transitions until a step sees them
+    for (pending, controller) in world.pending_transitions.iter_mut().zip(&game.controllers) {
+        for (count, button) in pending.iter_mut().zip(&controller.buttons) {
+            *count = count.saturating_add(button.half_transition_count);
+        }
+    }
 
-    // Get the world structure which is always at the beginning of the persistent memory
-    let world = unsafe {
-        &mut *(MEMORY_BASE_ADDR as *mut u8)
-            .cast::<World<TILE_MAP_COLUMNS, TILE_MAP_ROWS>>()
-    };
+    // Simulate the real time since the last frame in fixed steps, carrying the time left
+    // over into the next frame
+    world.accumulator += game.dt_seconds.clamp(0.0, SECONDS_PER_STEP * MAX_STEPS_PER_FRAME);
+    while world.accumulator >= SECONDS_PER_STEP {
+        simulate(game, state, world);
+        world.accumulator -= SECONDS_PER_STEP;
+
+        // Only the first step after a transition sees it
+        world.pending_transitions = [[0; variant_count::<Button>()]; MAX_CONTROLLERS];
+    }
+
+    // Render the entities partway between the last two steps by the time left over
+    render(game, state, world, world.accumulator / SECONDS_PER_STEP)
+}
+
+/// Advance the game by one fixed step of [`SECONDS_PER_STEP`]
+fn simulate(game: &mut Game, state: &mut State, world: &mut GameWorld) {
+    timed_block!("simulate");
 
-    // Draw the tile map where the camera is facing
-    world.draw_tilemap_at_camera(game, state)?;
-    
     for entity_index in 0..state.next_entity {
         let entity_alive = state.entity_alive[entity_index];
 
@@ -375,15 +539,37 @@ fn _game_update_and_render(game: &mut Game, state: &mut State) -> Result<()> {
             continue; 
         }
 
+        // Entities without a controller get no input. The transitions are every one since
+        // the last step rather than only this frame's.
+        let controller = state.entity_controller(entity_index)
+            .map(|controller_index| {
+                let mut controller = game.controllers[controller_index];
+                for (button, count) in controller.buttons.iter_mut().zip(world.pending_transitions[controller_index]) {
+                    button.half_transition_count = count;
+                }
+                controller
+            })
+            .unwrap_or_default();
+
         // Get the current alive entity
         let entity = state.entities.get_mut(entity_index).unwrap_or_else(|| panic!("Invalid entity index: {entity_index}"));
 
-        // let mut movement_delta = Vector2::new(Meters::new(0.), Meters::new(0.));
-        let mut acceleration = Vector2::new(Meters::new(0.), Meters::new(0.));
+        // Remember where the entity was to interpolate from while rendering
+        entity.previous_position = entity.position;
 
-        for (button_id, is_pressed) in game.buttons.as_ref().iter().enumerate() {
-            // Not pressed, ignore the button
-            if !is_pressed {
+        // Start from the analog stick, facing the direction it is pushed furthest
+        let stick = controller.stick;
+        let mut acceleration = Vector2::new(Meters::new(stick.x), Meters::new(stick.y));
+
+        if stick.x.abs() > stick.y.abs() {
+            entity.direction = if stick.x > 0. { PlayerDirection::Right } else { PlayerDirection::Left };
+        } else if stick.y != 0. {
+            entity.direction = if stick.y > 0. { PlayerDirection::Back } else { PlayerDirection::Front };
+        }
+
+        for (button_id, button_state) in controller.buttons.iter().enumerate() {
+            // Not held or tapped this frame, ignore the button
+            if !button_state.is_down() && !button_state.was_pressed() {
                 continue;
             }
 
@@ -414,20 +600,47 @@ fn _game_update_and_render(game: &mut Game, state: &mut State) -> Result<()> {
                 Button::IncreaseSpeed => {
                     acceleration *= Meters::new(10.0);
                 }
+                Button::Start => {}
             }
         }
 
         // Move the entity based on the acceleration
-        move_entity(entity_index, world, game, state, acceleration);
-       
+        move_entity(entity_index, world, game, state, acceleration, Meters::new(SECONDS_PER_STEP));
+    }
+}
+
+/// Draw the world with each entity `alpha` of the way from its previous position to its
+/// current one
+fn render(game: &mut Game, state: &mut State, world: &mut GameWorld, alpha: f32) -> Result<()> {
+    timed_block!("render");
+
+    // Draw the background
+    game.background.draw(game, Vector2::new(0., 0.));
+
+    // Draw the tile map where the camera is facing
+    world.draw_tilemap_at_camera(game, state)?;
+
+    for entity_index in 0..state.next_entity {
+        let entity_alive = state.entity_alive[entity_index];
+
+        // No need to draw this entity since it isn't alive
+        if !entity_alive { 
+            continue; 
+        }
+
+        let entity   = state.entities[entity_index];
+        let position = entity.previous_position.lerp(&entity.position, alpha);
+
+        // DEBUG draw the left edges of the tiles swept through by the last step
+        draw_swept_tiles(game, &entity)?;
+
         let tile_half = Vector2::new(f32::from(TILE_HALF_WIDTH), f32::from(TILE_HALF_HEIGHT));
   
         // DEBUG player position
-        let entity = state.entities.get_mut(entity_index).unwrap_or_else(|| panic!("Invalid entity index: {entity_index}"));
         draw_rectangle(
             game,
             &Color::BLACK,
-            entity.position.tile_center() - tile_half,
+            position.tile_center() - tile_half,
             f32::from(TILE_WIDTH),
             f32::from(TILE_HEIGHT),
         )?;
@@ -435,13 +648,73 @@ fn _game_update_and_render(game: &mut Game, state: &mut State) -> Result<()> {
         // Get the player bitmap for the direction they are currently facing
         let player_asset = game.player_assets[entity.direction as usize];
 
-        let position = entity.position.bottom_center() - player_asset.merge_point;
-        player_asset.head.draw(game, position);
-        player_asset.torso.draw(game, position);
-        player_asset.cape.draw(game, position);
+        let bitmap_position = position.bottom_center() - player_asset.merge_point;
+        player_asset.head.draw(game, bitmap_position);
+        player_asset.torso.draw(game, bitmap_position);
+        player_asset.cape.draw(game, bitmap_position);
 
         // DEBUG draw the player bottom center
-        draw_rectangle(game, &Color::RED, entity.position.bottom_center() - 2.0, 4.0, 4.0)?;
+        draw_rectangle(game, &Color::RED, position.bottom_center() - 2.0, 4.0, 4.0)?;
+    }
+
+    Ok(())
+}
+
+/// Debug function to draw the left edges of every tile `entity` may have moved through
+/// from its previous position to its current one
+fn draw_swept_tiles(game: &mut Game, entity: &Entity) -> Result<()> {
+    let old_player     = entity.previous_position;
+    let new_player_pos = entity.position;
+
+    let min_tile_x = old_player.tile_map_x.min(new_player_pos.tile_map_x);
+    let mut max_tile_x = old_player.tile_map_x.max(new_player_pos.tile_map_x);
+
+    let min_tile_y = old_player.tile_map_y.min(new_player_pos.tile_map_y);
+    let mut max_tile_y = old_player.tile_map_y.max(new_player_pos.tile_map_y);
+    max_tile_x.adjust(1);
+    max_tile_y.adjust(1);
+
+    // Look at all possible tiles moved through when moving from old -> new
+    let mut tile_x = min_tile_x;
+
+    loop {
+        if tile_x == max_tile_x {
+            break;
+        }
+
+        let mut tile_y = min_tile_y;
+        loop {
+            if tile_y == max_tile_y {
+                break;
+            }
+
+            // Get the current tile to check edges
+            let mut pos = old_player;
+            pos.tile_map_x = tile_x;
+            pos.tile_map_y = tile_y; 
+
+            let (c1, c2) = pos.left_edge();
+
+            draw_rectangle(
+                game,
+                &Color::RED,
+                c1,
+                10.0,
+                10.0
+            )?;
+
+            draw_rectangle(
+                game,
+                &Color::RED,
+                c2,
+                10.0,
+                10.0
+            )?;
+            
+            tile_y.adjust(1);
+        }
+
+        tile_x.adjust(1);
     }
 
     Ok(())
@@ -529,7 +802,7 @@ fn _draw_asset(game: &mut Game, asset: &BitmapAsset, pos_x: f32, pos_y: f32) ->
     let mut starting_height = (asset.height - height.trunc_as_u32()) as usize;
     #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
     if height + pos_y > game_height {
-        let offscreen = height + pos_y - game_height as f32;
+        let offscreen = height + pos_y - game_height;
         starting_height += offscreen as usize;
     }
 
@@ -572,7 +845,7 @@ fn _draw_asset(game: &mut Game, asset: &BitmapAsset, pos_x: f32, pos_y: f32) ->
 
         // In the event the image is off the left edge of the screen, the starting column
         // should be the remaining portion of the image not NOT from zero.
-        let starting_column = starting_column as usize * bytes_per_color;
+        let starting_column = starting_column * bytes_per_color;
 
         let mut pixels = &pixels_start[this_row + starting_column..];
 
@@ -610,13 +883,16 @@ fn _draw_asset(game: &mut Game, asset: &BitmapAsset, pos_x: f32, pos_y: f32) ->
     Ok(())
 }
 
-/// Move an entity based on the given acceleration
+/// Move an entity based on the given acceleration over `delta_t` seconds
 pub fn move_entity<const W: usize, const H: usize>(
         entity_index: usize, 
         world: &mut World<W, H>, 
         game: &mut Game, 
         state: &mut State, 
-        mut acceleration: Vector2<Meters>) {
+        mut acceleration: Vector2<Meters>,
+        delta_t: Meters) {
+    timed_block!("move_entity");
+
     let entity = &mut state.entities[entity_index];
 
     let old_player = entity.position;
@@ -646,15 +922,15 @@ pub fn move_entity<const W: usize, const H: usize>(
     let mut new_player_pos = entity.position;
     let move_delta = 
         // 0.5 * a * t^2
-        acceleration * Meters::new(0.5) * world.delta_t.powi(2).into() 
+        acceleration * Meters::new(0.5) * delta_t.powi(2).into() 
         // v * t
-        + entity.velocity * world.delta_t;
+        + entity.velocity * delta_t;
 
     // Add the delta for the new position
     new_player_pos.tile_rel += move_delta;
 
     // Use the velocity equation to calculate the new player velocity
-    entity.velocity = acceleration * world.delta_t + entity.velocity;
+    entity.velocity = acceleration * delta_t + entity.velocity;
 
     // Update the player coordinates based on the movement. If the player has stepped
     // beyond the bounds of the current tile, update the position to the new tile.
@@ -663,66 +939,13 @@ pub fn move_entity<const W: usize, const H: usize>(
 
     // assert!(old_player.tile_map_x.into_chunk().chunk_id == new_player_pos.tile_map_x.into_chunk().chunk_id);
 
-    let min_tile_x = old_player.tile_map_x.min(new_player_pos.tile_map_x);
-    let mut max_tile_x = old_player.tile_map_x.max(new_player_pos.tile_map_x);
-
-    let min_tile_y = old_player.tile_map_y.min(new_player_pos.tile_map_y);
-    let mut max_tile_y = old_player.tile_map_y.max(new_player_pos.tile_map_y);
-    max_tile_x.adjust(1);
-    max_tile_y.adjust(1);
-
-    // Look at all possible tiles moved through when moving from old -> new
-    // let tile_half = Vector2::new(f32::from(TILE_HALF_WIDTH), f32::from(TILE_HALF_HEIGHT));
-    let mut tile_x = min_tile_x;
-
-    // let mut tiles = vec![(old_player.tile_map_x, old_player.tile_map_y)];
-    loop {
-        if tile_x == max_tile_x {
-            break;
-        }
-
-        let mut tile_y = min_tile_y;
-        loop {
-            if tile_y == max_tile_y {
-                break;
-            }
-
-            // Get the current tile to check edges
-            let mut pos = entity.position;
-            pos.tile_map_x = tile_x;
-            pos.tile_map_y = tile_y; 
-
-            let (c1, c2) = pos.left_edge();
-
-            draw_rectangle(
-                game,
-                &Color::RED,
-                c1,
-                10.0,
-                10.0
-            ).unwrap();
-
-            draw_rectangle(
-                game,
-                &Color::RED,
-                c2,
-                10.0,
-                10.0
-            ).unwrap();
-            
-            tile_y.adjust(1);
-        }
-
-        tile_x.adjust(1);
-    }
-
     // Check that the potential moved to tile is valid (aka, zero)
     let mut valid = true;
 
     let ChunkVector { chunk_id, offset } = new_player_pos.into_chunk();
 
     // Get the tile map this player is on
-    let tile_map = world.get_tilemap_at(chunk_id, new_player_pos.z, &mut game.memory, &mut state.rng);
+    let tile_map = world.get_tilemap_at(chunk_id, new_player_pos.z, game.memory, state.seed);
 
     // Get the tile type for the destination tile
     let next_tile = tile_map.get_tile_at(offset);
@@ -743,6 +966,18 @@ pub fn move_entity<const W: usize, const H: usize>(
     // If the move is valid, update the player
     if valid {
         entity.position = new_player_pos;
+
+        // Take a step every stride, with a slightly different pitch for each step
+        let moved = new_player_pos.offset_from(&old_player);
+        entity.stride += Meters::new(moved.len_squared().sqrt());
+
+        if entity.stride >= FOOTSTEP_STRIDE {
+            entity.stride = Meters::new(0.0);
+
+            #[allow(clippy::cast_precision_loss)]
+            let pitch = 0.9 + (state.rng.next() % 21) as f32 / 100.0;
+            world.mixer.play(SoundEffect::Footstep, 0.5, pitch, Some(new_player_pos));
+        }
     } else {
         // Hit an object/wall
         let mut reflection = Vector2::new(Meters::new(0.0), Meters::new(0.0));
@@ -780,5 +1015,153 @@ pub fn move_entity<const W: usize, const H: usize>(
         let old_v = entity.velocity;
         entity.velocity = old_v
             - reflection * old_v.dot(reflection) * Meters::new(reaction_const);
+
+        // Bump into the wall louder the faster the player ran into it, fading out
+        // over the length of the thump. The reflection only gives the axis of the wall,
+        // not which side of it the player hit.
+        let impact_speed = old_v.dot(reflection).abs();
+        if impact_speed >= BUMP_MIN_SPEED {
+            let volume = (impact_speed / BUMP_FULL_SPEED).min(1.0);
+            if let Some(bump) = world.mixer.play(SoundEffect::Bump, volume, 1.0, Some(old_player)) {
+                world.mixer.fade_to(bump, 0.0, 0.2);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::{AbsoluteTile, Controller, Mouse, PlayerBitmap, Profiler, SoundAsset, WorldPosition};
+    use std::sync::PoisonError;
+
+    /// The game memory can only be mapped once per process, so tests take turns using it
+    static MEMORY: Mutex<Option<Memory>> = Mutex::new(None);
+
+    /// Empty bitmap standing in for the game's art
+    const BITMAP: BitmapAsset<'static> = BitmapAsset {
+        width:       0,
+        height:      0,
+        red_index:   0,
+        blue_index:  0,
+        green_index: 0,
+        alpha_index: 0,
+        data:        &[],
+    };
+
+    /// Empty sound standing in for the game's sound effects
+    const SOUND: SoundAsset<'static> = SoundAsset {
+        samples_per_second: 48_000,
+        channels:           1,
+        bits_per_sample:    16,
+        data:               &[],
+    };
+
+    /// Run `test` with a [`Game`] using freshly reset memory and a new world
+    fn with_game(test: impl FnOnce(&mut Game, &mut GameWorld)) {
+        let mut memory = MEMORY.lock().unwrap_or_else(PoisonError::into_inner);
+        let memory = memory.get_or_insert_with(Memory::new);
+        memory.reset();
+
+        let player = PlayerBitmap { head: BITMAP, torso: BITMAP, cape: BITMAP, merge_point: Vector2::new(0., 0.) };
+
+        let mut game = Game {
+            framebuffer:   &mut [],
+            width:         0,
+            height:        0,
+            dt_seconds:    SECONDS_PER_STEP,
+            error:         Ok(()),
+            mouse:         Mouse::default(),
+            controllers:   [Controller::default(); MAX_CONTROLLERS],
+            memory,
+            profiler:      Profiler::leak(),
+            player_assets: [&player; variant_count::<PlayerDirection>()],
+            background:    &BITMAP,
+            sound_assets:  [&SOUND; variant_count::<SoundEffect>()],
+        };
+
+        let (mut world, _) = game.memory.root::<GameWorld>(WORLD_LAYOUT, WORLD_MIGRATIONS);
+        world.init();
+
+        test(&mut game, &mut world);
+    }
+
+    /// Get the tiles of the room at `chunk` on floor `z` of `world` generated from `seed`
+    fn room_tiles(game: &mut Game, world: &mut GameWorld, seed: u64, chunk: Vector2<u32>, z: u8) -> Vec<u8> {
+        let tile_map = world.get_tilemap_at(chunk, z, game.memory, seed);
+        tile_map.data.iter().flatten().map(|tile| *tile as u8).collect()
+    }
+
+    #[test]
+    fn rooms_ignore_visit_order() {
+        let seed  = 0x1234;
+        let rooms = [(Vector2::new(0, 0), 0), (Vector2::new(1, 0), 0), (Vector2::new(0, 1), 0), (Vector2::new(0, 0), 1)];
+
+        // Visit the rooms forwards and then backwards, each in a new world
+        let mut forwards = Vec::new();
+        with_game(|game, world| {
+            forwards = rooms.iter().map(|(chunk, z)| room_tiles(game, world, seed, *chunk, *z)).collect();
+        });
+
+        let mut backwards = Vec::new();
+        with_game(|game, world| {
+            backwards = rooms.iter().rev().map(|(chunk, z)| room_tiles(game, world, seed, *chunk, *z)).collect();
+        });
+        backwards.reverse();
+
+        assert_eq!(forwards, backwards);
+    }
+
+    #[test]
+    fn seeds_generate_different_rooms() {
+        with_game(|game, world| {
+            let chunk = Vector2::new(0, 0);
+            let first = room_tiles(game, world, 1, chunk, 0);
+
+            world.init();
+            let second = room_tiles(game, world, 2, chunk, 0);
+
+            assert_ne!(first, second);
+        });
+    }
+
+    /// Position of the tile at (`x`, `y`) in the first room
+    fn tile_position(x: u32, y: u32) -> WorldPosition {
+        WorldPosition {
+            tile_map_x: AbsoluteTile::from_chunk_offset(0, x),
+            tile_map_y: AbsoluteTile::from_chunk_offset(0, y),
+            z:          0,
+            tile_rel:   Vector2::new(Meters::new(0.0), Meters::new(0.0)),
+        }
+    }
+
+    #[test]
+    fn bump_into_each_wall() {
+        // Start next to each of the outer walls of the first room, clear of its doors,
+        // running into the wall
+        let last_column = u32::try_from(TILE_MAP_COLUMNS).unwrap() - 1;
+        let last_row    = u32::try_from(TILE_MAP_ROWS).unwrap() - 1;
+        let walls = [
+            ("left",   tile_position(1, 2),               Vector2::new(-20.0, 0.0)),
+            ("right",  tile_position(last_column - 1, 2), Vector2::new(20.0, 0.0)),
+            ("bottom", tile_position(2, 1),               Vector2::new(0.0, -20.0)),
+            ("top",    tile_position(2, last_row - 1),    Vector2::new(0.0, 20.0)),
+        ];
+
+        for (wall, position, velocity) in walls {
+            with_game(|game, world| {
+                let mut state = State::reset(0x1234);
+                let player    = state.add_player(0);
+
+                state.entities[player].position = position;
+                state.entities[player].velocity = Vector2::new(Meters::new(velocity.x), Meters::new(velocity.y));
+
+                let no_input = Vector2::new(Meters::new(0.0), Meters::new(0.0));
+                move_entity(player, world, game, &mut state, no_input, Meters::new(SECONDS_PER_STEP));
+
+                assert_eq!(state.entities[player].position, position, "moved through the {wall} wall");
+                assert!(world.mixer.is_playing(SoundEffect::Bump), "no bump from the {wall} wall");
+            });
+        }
     }
 }
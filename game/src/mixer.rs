@@ -0,0 +1,347 @@
+//! Software mixer playing sound effects into the platform's [`SoundBuffer`]
+//!
+//! Each playing sound has its own volume (which can fade towards a target over time),
+//! pitch, and an optional [`WorldPosition`] used to pan the sound left or right of the
+//! camera. The samples come from the [`SoundAsset`] of each [`SoundEffect`].
+
+use core::mem::variant_count;
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+use game_state::{SoundAsset, SoundBuffer, SoundEffect, WorldPosition};
+use game_state::{SOUND_CHANNELS, TILE_MAP_COLUMNS, TILE_SIDE_IN_METERS};
+
+/// Maximum number of sounds playing at once. New sounds are dropped while full.
+const MAX_PLAYING_SOUNDS: usize = 32;
+
+/// Slowest pitch a sound can be played at, so every sound eventually finishes
+const MIN_PITCH: f32 = 0.1;
+
+/// Amplitude of a sound played at a volume of 1.0
+const FULL_VOLUME: f32 = 8000.0;
+
+/// Index of a sound playing in the [`Mixer`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SoundHandle(usize);
+
+/// A sound effect being played by the [`Mixer`]
+#[derive(Debug, Copy, Clone)]
+struct PlayingSound {
+    /// The effect being played
+    effect: SoundEffect,
+
+    /// Position in the effect's sample frames. Fractional when the pitch or sample rate
+    /// differs from the output.
+    cursor: f32,
+
+    /// Multiplier of the rate the effect's samples are stepped through
+    pitch: f32,
+
+    /// Current volume, where 1.0 is full volume
+    volume: f32,
+
+    /// Volume the sound is fading towards
+    target_volume: f32,
+
+    /// Change in volume per second while fading
+    fade_per_second: f32,
+
+    /// Where the sound is in the world, or `None` to play it at the camera
+    position: Option<WorldPosition>,
+}
+
+impl PlayingSound {
+    /// Get the left and right volume of this sound heard from the `camera`
+    fn pan(&self, camera: &WorldPosition) -> (f32, f32) {
+        let Some(position) = self.position else {
+            return (1.0, 1.0);
+        };
+
+        // Sounds at the edge of the screen are panned fully to one side
+        #[allow(clippy::cast_precision_loss)]
+        let pan_distance = *TILE_SIDE_IN_METERS * TILE_MAP_COLUMNS as f32 / 2.0;
+
+        let offset = position.offset_from(camera);
+        let pan    = (*offset.x / pan_distance).clamp(-1.0, 1.0);
+
+        // Constant power panning keeps the sound as loud while moving across the screen
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        (angle.cos() * SQRT_2, angle.sin() * SQRT_2)
+    }
+}
+
+/// Mixes the currently playing sounds
+#[derive(Debug, Copy, Clone)]
+pub struct Mixer {
+    /// Sounds currently playing
+    sounds: [Option<PlayingSound>; MAX_PLAYING_SOUNDS],
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self { sounds: [None; MAX_PLAYING_SOUNDS] }
+    }
+}
+
+impl Mixer {
+    /// Start playing `effect` at the given `volume` and `pitch` (1.0 being the original
+    /// pitch, and at least [`MIN_PITCH`]), panned by its `position` in the world. Returns
+    /// `None` if too many sounds are already playing.
+    pub fn play(&mut self, effect: SoundEffect, volume: f32, pitch: f32, position: Option<WorldPosition>) -> Option<SoundHandle> {
+        let index = self.sounds.iter().position(Option::is_none)?;
+
+        self.sounds[index] = Some(PlayingSound {
+            effect,
+            cursor:          0.0,
+            pitch:           pitch.max(MIN_PITCH),
+            volume,
+            target_volume:   volume,
+            fade_per_second: 0.0,
+            position,
+        });
+
+        Some(SoundHandle(index))
+    }
+
+    /// Whether `effect` is one of the sounds playing
+    #[cfg(test)]
+    pub fn is_playing(&self, effect: SoundEffect) -> bool {
+        self.sounds.iter().flatten().any(|sound| sound.effect == effect)
+    }
+
+    /// Fade the volume of the sound at `handle` to `volume` over `seconds`. A sound faded
+    /// to silence stops playing.
+    pub fn fade_to(&mut self, handle: SoundHandle, volume: f32, seconds: f32) {
+        let Some(sound) = &mut self.sounds[handle.0] else {
+            return;
+        };
+
+        sound.target_volume = volume;
+
+        if seconds > 0.0 {
+            sound.fade_per_second = (volume - sound.volume).abs() / seconds;
+        } else {
+            sound.volume = volume;
+        }
+    }
+
+    /// Mix the playing sounds, heard from the `camera`, into the `buffer` using the
+    /// samples in `assets`
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn mix(
+            &mut self,
+            camera: &WorldPosition,
+            assets: &[&SoundAsset; variant_count::<SoundEffect>()],
+            buffer: &mut SoundBuffer) {
+        let mut mixed = vec![0.0_f32; buffer.samples.len()];
+
+        for slot in &mut self.sounds {
+            let Some(sound) = slot else {
+                continue;
+            };
+
+            let asset = assets[sound.effect as usize];
+
+            // Step through the asset at its own sample rate regardless of the output rate
+            let rate_step = asset.samples_per_second as f32 / buffer.samples_per_second as f32;
+
+            let (left, right) = sound.pan(camera);
+            let fade_step     = sound.fade_per_second / buffer.samples_per_second as f32;
+            let len           = asset.frame_count();
+
+            for frame in mixed.chunks_exact_mut(SOUND_CHANNELS) {
+                let index = sound.cursor as usize;
+                if index >= len {
+                    break;
+                }
+
+                // Linearly interpolate between sample frames that the cursor falls between
+                let fract = sound.cursor.fract();
+                for (channel, (out, pan)) in frame.iter_mut().zip([left, right]).enumerate() {
+                    let curr = asset.sample(index, channel);
+                    let next = asset.sample(index + 1, channel);
+                    *out += (curr + (next - curr) * fract) * sound.volume * pan;
+                }
+
+                sound.cursor += sound.pitch * rate_step;
+
+                // Move the volume towards the target without overshooting it
+                if sound.volume < sound.target_volume {
+                    sound.volume = (sound.volume + fade_step).min(sound.target_volume);
+                } else {
+                    sound.volume = (sound.volume - fade_step).max(sound.target_volume);
+                }
+            }
+
+            // Stop sounds that finished or faded out
+            let finished = sound.cursor as usize >= len;
+            let silent   = sound.volume <= 0.0 && sound.target_volume <= 0.0;
+            if finished || silent {
+                *slot = None;
+            }
+        }
+
+        for (out, sample) in buffer.samples.iter_mut().zip(mixed) {
+            *out = (sample * FULL_VOLUME).clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::AbsoluteTile;
+
+    /// Sample frames of every test sound
+    const FRAMES: usize = 8;
+
+    /// 8 bit mono samples at half volume
+    const HALF: [u8; FRAMES] = [192; FRAMES];
+
+    /// A mono sound of [`HALF`] played at `samples_per_second`
+    fn asset(samples_per_second: u32) -> SoundAsset<'static> {
+        SoundAsset { samples_per_second, channels: 1, bits_per_sample: 8, data: &HALF }
+    }
+
+    /// Position of the tile `x` tiles into the first room, on its bottom row
+    fn position(x: u32) -> WorldPosition {
+        WorldPosition {
+            tile_map_x: AbsoluteTile::from_chunk_offset(0, x),
+            tile_map_y: AbsoluteTile::from_chunk_offset(0, 0),
+            ..WorldPosition::default()
+        }
+    }
+
+    /// Mix `frames` sample frames at 48 kHz of the sounds in `mixer` heard from the tile
+    /// `camera_x` tiles into the first room, using `asset` for every effect
+    fn mix(mixer: &mut Mixer, asset: &SoundAsset, camera_x: u32, frames: usize) -> Vec<i16> {
+        let mut samples = vec![0; frames * SOUND_CHANNELS];
+        let mut buffer  = SoundBuffer { samples_per_second: 48_000, samples: &mut samples };
+
+        mixer.mix(&position(camera_x), &[asset; variant_count::<SoundEffect>()], &mut buffer);
+        samples
+    }
+
+    /// A sound playing at full volume from `position`
+    fn sound(position: Option<WorldPosition>) -> PlayingSound {
+        PlayingSound {
+            effect:          SoundEffect::Footstep,
+            cursor:          0.0,
+            pitch:           1.0,
+            volume:          1.0,
+            target_volume:   1.0,
+            fade_per_second: 0.0,
+            position,
+        }
+    }
+
+    /// Number of sounds playing in `mixer`
+    fn playing(mixer: &Mixer) -> usize {
+        mixer.sounds.iter().flatten().count()
+    }
+
+    #[test]
+    fn finished_sound_frees_slot() {
+        let mut mixer = Mixer::default();
+        mixer.play(SoundEffect::Footstep, 1.0, 1.0, None);
+
+        let samples = mix(&mut mixer, &asset(48_000), 0, FRAMES * 2);
+        assert_eq!(playing(&mixer), 0);
+
+        // Every sample frame of the sound is heard on both channels, then silence
+        let half = (0.5 * FULL_VOLUME) as i16;
+        assert!(samples[..FRAMES * SOUND_CHANNELS].iter().all(|sample| *sample == half));
+        assert!(samples[FRAMES * SOUND_CHANNELS..].iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn resamples_to_output_rate() {
+        // A sound at half the output rate takes twice as many output frames
+        let mut mixer = Mixer::default();
+        mixer.play(SoundEffect::Footstep, 1.0, 1.0, None);
+
+        mix(&mut mixer, &asset(24_000), 0, FRAMES);
+        assert_eq!(playing(&mixer), 1);
+
+        mix(&mut mixer, &asset(24_000), 0, FRAMES);
+        assert_eq!(playing(&mixer), 0);
+    }
+
+    #[test]
+    fn pitch_is_clamped() {
+        // A sound played without any pitch still finishes, at the slowest pitch
+        let mut mixer = Mixer::default();
+        mixer.play(SoundEffect::Footstep, 1.0, 0.0, None);
+        assert_eq!(mixer.sounds[0].unwrap().pitch, MIN_PITCH);
+
+        mix(&mut mixer, &asset(48_000), 0, FRAMES * 9);
+        assert_eq!(playing(&mixer), 1);
+
+        mix(&mut mixer, &asset(48_000), 0, FRAMES * 2);
+        assert_eq!(playing(&mixer), 0);
+    }
+
+    #[test]
+    fn full_mixer_drops_sounds() {
+        let mut mixer = Mixer::default();
+
+        for index in 0..MAX_PLAYING_SOUNDS {
+            assert_eq!(mixer.play(SoundEffect::Bump, 1.0, 1.0, None), Some(SoundHandle(index)));
+        }
+        assert_eq!(mixer.play(SoundEffect::Bump, 1.0, 1.0, None), None);
+
+        // Finished sounds make room for new ones
+        mix(&mut mixer, &asset(48_000), 0, FRAMES);
+        assert_eq!(mixer.play(SoundEffect::Bump, 1.0, 1.0, None), Some(SoundHandle(0)));
+    }
+
+    #[test]
+    fn fade_to_silence_stops() {
+        let mut mixer = Mixer::default();
+        let handle    = mixer.play(SoundEffect::Bump, 1.0, 0.1, None).unwrap();
+
+        // Fade out over half the frames before the sound would finish
+        let fade_frames = FRAMES * 5;
+        #[allow(clippy::cast_precision_loss)]
+        mixer.fade_to(handle, 0.0, fade_frames as f32 / 48_000.0);
+
+        let samples = mix(&mut mixer, &asset(48_000), 0, fade_frames + 2);
+        assert_eq!(playing(&mixer), 0);
+
+        // Quieter every frame until silent
+        let left = samples.iter().step_by(SOUND_CHANNELS).collect::<Vec<_>>();
+        assert!(left.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(*left[0] > 0);
+        assert_eq!(*left[fade_frames], 0);
+    }
+
+    #[test]
+    fn pan_by_position() {
+        let mut mixer = Mixer::default();
+        mixer.play(SoundEffect::Footstep, 1.0, 1.0, Some(position(2)));
+
+        // Left of the camera is louder in the left channel
+        let samples = mix(&mut mixer, &asset(48_000), 8, 1);
+        assert!(samples[0] > samples[1], "{samples:?}");
+
+        // Right of the camera is louder in the right channel
+        let mut mixer = Mixer::default();
+        mixer.play(SoundEffect::Footstep, 1.0, 1.0, Some(position(14)));
+        let samples = mix(&mut mixer, &asset(48_000), 8, 1);
+        assert!(samples[0] < samples[1], "{samples:?}");
+    }
+
+    #[test]
+    fn pan_keeps_constant_power() {
+        let camera = position(8);
+
+        for x in 0..16 {
+            let (left, right) = sound(Some(position(x))).pan(&camera);
+            assert!((left * left + right * right - 2.0).abs() < 1e-5, "{x}: {left} {right}");
+        }
+
+        // Sounds at the camera are centered, and sounds without a position aren't panned
+        let (left, right) = sound(Some(camera)).pan(&camera);
+        assert!((left - right).abs() < 1e-6);
+        assert_eq!(sound(None).pan(&camera), (1.0, 1.0));
+    }
+}
@@ -1,6 +1,5 @@
 //! Shared game state information between platforms and game logic
 
-#![feature(const_fn_floating_point_arithmetic)]
 #![feature(variant_count)]
 
 use serde::{Deserialize, Serialize};
@@ -15,7 +14,11 @@ pub use rng::Rng;
 use vector::Vector2;
 
 mod memory;
-pub use memory::{Allocation, Memory, MEMORY_BASE_ADDR, MEMORY_LENGTH};
+pub use memory::{root_layout, Allocation, Memory, Migration, RootStatus};
+pub use memory::{MEMORY_BASE_ADDR, MEMORY_LENGTH};
+
+pub mod profile;
+pub use profile::Profiler;
 
 /// Number of COLUMNS in the tile map
 pub const TILE_MAP_COLUMNS: usize = 16;
@@ -79,15 +82,90 @@ pub const TARGET_FRAMES_PER_SECOND: f32 = 30.0;
 pub const PIXELS_PER_METER: PixelsPerMeter =
     PixelsPerMeter::new(TILE_SIDE_IN_PIXELS, TILE_SIDE_IN_METERS);
 
+/// Sample rate of the game sound
+pub const SAMPLES_PER_SECOND: u32 = 48_000;
+
+/// Number of interleaved channels in a [`SoundBuffer`], left then right
+pub const SOUND_CHANNELS: usize = 2;
+
 /// Number of microseconds available per frame
 ///
 /// Acutally do want this to truncate
 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 pub const MILLISECONDS_PER_FRAME: f32 = 1.0 / TARGET_FRAMES_PER_SECOND * 1000.;
 
+/// Number of controllers: the keyboard followed by the gamepads
+pub const MAX_CONTROLLERS: usize = 5;
+
+/// Index of the controller driven by the keyboard
+pub const KEYBOARD_CONTROLLER: usize = 0;
+
 /// Size (in bytes) of the State struct
 pub const STATE_SIZE: usize = std::mem::size_of::<State>();
 
+/// Size (in bytes) of the Game struct
+pub const GAME_SIZE: usize = std::mem::size_of::<Game<'static>>();
+
+/// Version of the interface between the platform and the game library. Bump this when
+/// the meaning of the exported functions or their arguments changes without changing
+/// their layout.
+pub const GAME_ABI_VERSION: u32 = 3;
+
+/// Starting value for a [`fnv1a`] hash
+pub const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continue the FNV-1a `hash` over the given `bytes`
+pub const fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut index = 0;
+    while index < bytes.len() {
+        hash ^= bytes[index] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        index += 1;
+    }
+
+    hash
+}
+
+/// Hash of the layout of the data shared between the platform and the game logic, such
+/// as the [`State`] and the persistent memory. Data written with a different hash can't
+/// be trusted by this build.
+///
+/// Only the sizes, alignments, and counts below are hashed, along with the package
+/// version. It is not a hash of the build itself, so a change that keeps every size the
+/// same (such as reordering fields) must bump the package version or [`GAME_ABI_VERSION`].
+pub const LAYOUT_HASH: u64 = {
+    let layout = [
+        STATE_SIZE,
+        std::mem::align_of::<State>(),
+        GAME_SIZE,
+        std::mem::align_of::<Game<'static>>(),
+        std::mem::size_of::<Entity>(),
+        std::mem::size_of::<WorldPosition>(),
+        std::mem::size_of::<Rng>(),
+        ENTITY_COUNT,
+        MEMORY_BASE_ADDR,
+        MEMORY_LENGTH,
+        variant_count::<Button>(),
+        std::mem::size_of::<ButtonState>(),
+        std::mem::size_of::<Controller>(),
+        MAX_CONTROLLERS,
+        std::mem::size_of::<SoundBuffer<'static>>(),
+        SOUND_CHANNELS,
+        variant_count::<PlayerDirection>(),
+        variant_count::<SoundEffect>(),
+        std::mem::size_of::<Profiler>(),
+    ];
+
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, env!("CARGO_PKG_VERSION").as_bytes());
+    let mut index = 0;
+    while index < layout.len() {
+        hash = fnv1a(hash, &layout[index].to_le_bytes());
+        index += 1;
+    }
+
+    hash
+};
+
 /// Provides the `truncate` trait for rounding `f32` to `u32`
 pub trait Truncate {
     /// Truncate the given value
@@ -121,6 +199,24 @@ impl Round for f32 {
 pub enum Error {
     /// Attempted to draw an invalid rectangle
     InvalidRectangle,
+
+    /// The game logic panicked
+    Panic {
+        /// The panic message
+        message: String,
+
+        /// The source location of the panic
+        location: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidRectangle => write!(f, "attempted to draw an invalid rectangle"),
+            Error::Panic { message, location } => write!(f, "panicked at {location}: {message}"),
+        }
+    }
 }
 
 /// Custom [`Result`] type for the game logic
@@ -196,7 +292,7 @@ impl<'a> BitmapAsset<'a> {
         let mut starting_height = (self.height - height.trunc_as_u32()) as usize;
         #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
         if height + pos.y > game_height {
-            let offscreen = height + pos.y - game_height as f32;
+            let offscreen = height + pos.y - game_height;
             starting_height += offscreen as usize;
         }
 
@@ -237,7 +333,7 @@ impl<'a> BitmapAsset<'a> {
 
             // In the event the image is off the left edge of the screen, the starting column
             // should be the remaining portion of the image not NOT from zero.
-            let starting_column = starting_column as usize * bytes_per_color;
+            let starting_column = starting_column * bytes_per_color;
 
             let mut pixels = &pixels_start[this_row + starting_column..];
 
@@ -359,10 +455,278 @@ impl<'a> PlayerBitmap<'a> {
     }
 }
 
+/// Fastest sample rate accepted for a [`SoundAsset`]
+pub const MAX_SOUND_SAMPLES_PER_SECOND: u32 = 192_000;
+
+/// `WAVE_FORMAT_PCM` format tag of uncompressed WAV samples
+const WAVE_FORMAT_PCM: u16 = 1;
+
+/// Errors that can occur while parsing a WAV file into a [`SoundAsset`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WavError {
+    /// The data doesn't start with a RIFF/WAVE header
+    NotWave,
+
+    /// The chunk with the given id runs past the end of the data
+    TruncatedChunk([u8; 4]),
+
+    /// The chunk with the given id is missing
+    MissingChunk(&'static str),
+
+    /// The `fmt ` chunk is too small to describe the samples
+    InvalidFormatChunk(usize),
+
+    /// The samples are compressed with the given format tag
+    UnsupportedFormat(u16),
+
+    /// Samples with the given number of bits aren't supported
+    UnsupportedBitsPerSample(u16),
+
+    /// Sounds with the given number of channels aren't supported
+    UnsupportedChannels(u16),
+
+    /// The sample rate is zero or above [`MAX_SOUND_SAMPLES_PER_SECOND`]
+    InvalidSampleRate(u32),
+
+    /// The block alignment doesn't match the channels and bits per sample
+    InvalidBlockAlign(u16),
+}
+
+impl std::fmt::Display for WavError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WavError::NotWave => write!(f, "not a RIFF/WAVE file"),
+            WavError::TruncatedChunk(id) => {
+                write!(
+                    f,
+                    "{} chunk is truncated",
+                    String::from_utf8_lossy(id).trim_end()
+                )
+            }
+            WavError::MissingChunk(id) => write!(f, "missing {} chunk", id.trim_end()),
+            WavError::InvalidFormatChunk(size) => write!(f, "fmt chunk too small: {size} bytes"),
+            WavError::UnsupportedFormat(tag) => write!(f, "unsupported format {tag:#x}, not PCM"),
+            WavError::UnsupportedBitsPerSample(bits) => {
+                write!(f, "unsupported {bits} bits per sample, expected 8 or 16")
+            }
+            WavError::UnsupportedChannels(channels) => {
+                write!(
+                    f,
+                    "unsupported {channels} channels, expected mono or stereo"
+                )
+            }
+            WavError::InvalidSampleRate(rate) => write!(f, "invalid sample rate {rate}"),
+            WavError::InvalidBlockAlign(align) => write!(f, "invalid block align {align}"),
+        }
+    }
+}
+
+/// A sound asset of PCM samples borrowed from a WAV file
+pub struct SoundAsset<'a> {
+    /// Number of sample frames played per second
+    pub samples_per_second: u32,
+
+    /// Number of interleaved channels, 1 for mono or 2 for stereo
+    pub channels: u16,
+
+    /// Size of each sample, 8 (unsigned) or 16 (signed) bits
+    pub bits_per_sample: u16,
+
+    /// Reference to the interleaved samples
+    pub data: &'a [u8],
+}
+
+impl<'a> std::fmt::Debug for SoundAsset<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoundAsset")
+            .field("samples_per_second", &self.samples_per_second)
+            .field("channels", &self.channels)
+            .field("bits_per_sample", &self.bits_per_sample)
+            .field("data", &format!("{:p}", &self.data))
+            .finish()
+    }
+}
+
+impl<'a> SoundAsset<'a> {
+    /// Create a [`SoundAsset`] from the bytes of a WAV file
+    ///
+    /// # Errors
+    ///
+    /// * The data isn't a well formed RIFF/WAVE file
+    /// * The samples aren't 8 or 16 bit PCM in mono or stereo
+    pub fn from_data(data: &'a [u8]) -> std::result::Result<Self, WavError> {
+        let read_u16 = |bytes: &[u8], offset: usize| {
+            u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+        };
+        let read_u32 = |bytes: &[u8], offset: usize| {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(WavError::NotWave);
+        }
+
+        // Walk the chunks following the header, looking for the format and the samples
+        let mut format = None;
+        let mut samples = None;
+        let mut offset = 12;
+
+        while offset + 8 <= data.len() {
+            let id: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+            let size = read_u32(data, offset + 4);
+            let start = offset + 8;
+
+            let chunk = usize::try_from(size)
+                .ok()
+                .and_then(|size| data.get(start..start.checked_add(size)?))
+                .ok_or(WavError::TruncatedChunk(id))?;
+
+            match &id {
+                b"fmt " => format = Some(chunk),
+                b"data" => samples = Some(chunk),
+                _ => {}
+            }
+
+            // Chunks are padded to an even number of bytes
+            offset = start + chunk.len() + chunk.len() % 2;
+        }
+
+        let format = format.ok_or(WavError::MissingChunk("fmt "))?;
+        let samples = samples.ok_or(WavError::MissingChunk("data"))?;
+
+        if format.len() < 16 {
+            return Err(WavError::InvalidFormatChunk(format.len()));
+        }
+
+        let format_tag = read_u16(format, 0);
+        let channels = read_u16(format, 2);
+        let samples_per_second = read_u32(format, 4);
+        let block_align = read_u16(format, 12);
+        let bits_per_sample = read_u16(format, 14);
+
+        if format_tag != WAVE_FORMAT_PCM {
+            return Err(WavError::UnsupportedFormat(format_tag));
+        }
+
+        if !matches!(bits_per_sample, 8 | 16) {
+            return Err(WavError::UnsupportedBitsPerSample(bits_per_sample));
+        }
+
+        if !matches!(channels, 1 | 2) {
+            return Err(WavError::UnsupportedChannels(channels));
+        }
+
+        if samples_per_second == 0 || samples_per_second > MAX_SOUND_SAMPLES_PER_SECOND {
+            return Err(WavError::InvalidSampleRate(samples_per_second));
+        }
+
+        if block_align != channels * bits_per_sample / 8 {
+            return Err(WavError::InvalidBlockAlign(block_align));
+        }
+
+        // Drop a partial sample frame at the end of the data
+        let length = samples.len() - samples.len() % usize::from(block_align);
+
+        Ok(SoundAsset {
+            samples_per_second,
+            channels,
+            bits_per_sample,
+            data: &samples[..length],
+        })
+    }
+
+    /// Number of sample frames in the sound, each holding one sample per channel
+    pub fn frame_count(&self) -> usize {
+        self.data.len() / usize::from(self.channels * self.bits_per_sample / 8)
+    }
+
+    /// Get the sample in [-1.0, 1.0] of `channel` in sample `frame`. Mono sounds play the
+    /// same sample on every channel, and frames past the end of the sound are silent.
+    pub fn sample(&self, frame: usize, channel: usize) -> f32 {
+        let channel = channel.min(usize::from(self.channels) - 1);
+        let index = frame * usize::from(self.channels) + channel;
+
+        if self.bits_per_sample == 8 {
+            self.data
+                .get(index)
+                .map_or(0.0, |sample| (f32::from(*sample) - 128.0) / 128.0)
+        } else {
+            self.data
+                .get(index * 2..index * 2 + 2)
+                .map_or(0.0, |sample| {
+                    f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32768.0
+                })
+        }
+    }
+}
+
+/// Describes the interface a game library was built against. Exported by the game
+/// library as `GAME_ABI` so the platform can refuse a library built against a different
+/// layout of [`Game`] or [`State`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameAbi {
+    /// The [`GAME_ABI_VERSION`] of the build
+    pub version: u32,
+
+    /// Size (in bytes) of the [`State`] struct
+    pub state_size: u64,
+
+    /// Size (in bytes) of the [`Game`] struct
+    pub game_size: u64,
+
+    /// The [`LAYOUT_HASH`] of the build
+    pub layout_hash: u64,
+}
+
+impl GameAbi {
+    /// The interface of the current build
+    pub const CURRENT: GameAbi = GameAbi {
+        version: GAME_ABI_VERSION,
+        state_size: STATE_SIZE as u64,
+        game_size: GAME_SIZE as u64,
+        layout_hash: LAYOUT_HASH,
+    };
+
+    /// Get the first field that differs between this interface and the `expected`
+    /// interface as (name, this value, expected value)
+    pub fn mismatch(&self, expected: &GameAbi) -> Option<(&'static str, u64, u64)> {
+        [
+            (
+                "ABI version",
+                u64::from(self.version),
+                u64::from(expected.version),
+            ),
+            ("state size", self.state_size, expected.state_size),
+            ("game size", self.game_size, expected.game_size),
+            ("layout hash", self.layout_hash, expected.layout_hash),
+        ]
+        .into_iter()
+        .find(|(_, found, expected)| found != expected)
+    }
+}
+
+/// Buffer of sound samples filled by the game for the platform to play
+#[derive(Debug)]
+pub struct SoundBuffer<'a> {
+    /// Number of samples played per second on each channel
+    pub samples_per_second: u32,
+
+    /// Interleaved samples to fill, [`SOUND_CHANNELS`] per sample frame
+    pub samples: &'a mut [i16],
+}
+
+impl<'a> SoundBuffer<'a> {
+    /// Number of sample frames in the buffer, each holding one sample per channel
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / SOUND_CHANNELS
+    }
+}
+
 /// Game/Memory state
 pub struct Game<'a> {
     /// Framebuffer used for rendering to the window
-    pub framebuffer: &'a mut Vec<u32>,
+    pub framebuffer: &'a mut [u32],
 
     /// Width of the game window
     pub width: u16,
@@ -370,20 +734,33 @@ pub struct Game<'a> {
     /// Height of the game window
     pub height: u16,
 
+    /// Seconds of real time since the previous frame
+    pub dt_seconds: f32,
+
     /// Potential error when executing the game logic
     pub error: Result<()>,
 
-    /// Current buttons pressed
-    pub buttons: &'a [bool; variant_count::<Button>()],
+    /// Current mouse cursor position and buttons
+    pub mouse: Mouse,
+
+    /// State of each controller over this frame, starting with the keyboard at
+    /// [`KEYBOARD_CONTROLLER`]
+    pub controllers: [Controller; MAX_CONTROLLERS],
 
     /// Reference to the memory backing the game
     pub memory: &'a mut Memory,
 
+    /// Profiler the game records its timed blocks into
+    pub profiler: &'static Profiler,
+
     /// Player assets specific to the direction the player is facing
     pub player_assets: [&'a PlayerBitmap<'a>; variant_count::<PlayerDirection>()],
 
     /// Background asset
     pub background: &'a BitmapAsset<'a>,
+
+    /// Sound assets for each of the [`SoundEffect`]s
+    pub sound_assets: [&'a SoundAsset<'a>; variant_count::<SoundEffect>()],
 }
 
 impl From<f32> for Meters {
@@ -504,7 +881,7 @@ impl From<Meters> for f32 {
 
 impl From<Meters> for f64 {
     fn from(val: Meters) -> f64 {
-        val.into()
+        f64::from(val.0)
     }
 }
 
@@ -555,11 +932,17 @@ pub struct Entity {
     /// World position of the player
     pub position: WorldPosition,
 
+    /// World position of the player before the last simulation step
+    pub previous_position: WorldPosition,
+
     /// Direction the player is facing
     pub direction: PlayerDirection,
 
     /// The current velocity that the player is moving
     pub velocity: Vector2<Meters>,
+
+    /// Distance moved since the last footstep
+    pub stride: Meters,
 }
 
 impl Entity {
@@ -573,6 +956,7 @@ impl Entity {
             z: 0,
             tile_rel: Vector2::new(Meters::new(0.0), Meters::new(0.0)),
         };
+        self.previous_position = self.position;
         self.direction = PlayerDirection::Front;
         self.velocity = Vector2::new(Meters::new(0.0), Meters::new(0.0));
     }
@@ -598,25 +982,32 @@ pub struct State {
     /// Camera position to known where to draw the current screen
     pub camera: WorldPosition,
 
+    /// Seed the world and `rng` were generated from
+    pub seed: u64,
+
     /// Random number generator
     pub rng: Rng,
 
     /// Number of players in the game
     pub num_players: u32,
+
+    /// The player entity controlled by each controller, if any
+    pub controller_entities: [Option<usize>; MAX_CONTROLLERS],
 }
 
 impl State {
-    /// Get the beginning game state
+    /// Get the beginning game state for the world generated from `seed`
     ///
     /// # Panics
     ///
     /// If the screen center row or column doesn't fit in a u32 -- o.0
-    pub fn reset() -> Self {
+    pub fn reset(seed: u64) -> Self {
         Self {
             entity_alive: [false; 256],
             entities: [Entity::default(); 256],
             next_entity: 0,
             num_players: 0,
+            controller_entities: [None; MAX_CONTROLLERS],
             camera_following_entity: 0,
             camera: WorldPosition {
                 tile_map_x: AbsoluteTile::from_chunk_offset(
@@ -630,20 +1021,32 @@ impl State {
                 z: 0,
                 tile_rel: Vector2::new(Meters::new(0.0), Meters::new(0.0)),
             },
-            rng: Rng::new(),
+            seed,
+            rng: Rng::from_seed(seed),
         }
     }
 
-    /// Add a player to the game
-    pub fn add_player(&mut self) {
+    /// Add a player to the game controlled by the given `controller`, returning the index
+    /// of the player entity
+    pub fn add_player(&mut self, controller: usize) -> usize {
         let player_index = self.allocate_entity();
         self.num_players += 1;
         self.entities[player_index].init_as_player();
+        self.controller_entities[controller] = Some(player_index);
 
         // Always follow the first player
         if self.num_players == 1 {
             self.camera_following_entity = player_index;
         }
+
+        player_index
+    }
+
+    /// Get the controller of the player entity at `entity_index`, if any
+    pub fn entity_controller(&self, entity_index: usize) -> Option<usize> {
+        self.controller_entities
+            .iter()
+            .position(|entity| *entity == Some(entity_index))
     }
 
     /// Get the index of the an allocated entity from the entity list
@@ -680,7 +1083,7 @@ pub struct ChunkVector {
 }
 
 /// An absolute tile location in the world, constrained to only be [`0`, `MAX`) in value.
-#[derive(Copy, Clone, PartialEq, Eq, Default, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
 #[repr(transparent)]
 pub struct AbsoluteTile<const MAX_CHUNK_ID: usize, const MAX_OFFSET: usize>(u32);
 
@@ -711,6 +1114,16 @@ impl<const MAX_CHUNK_ID: usize, const MAX_OFFSET: usize> AbsoluteTile<MAX_CHUNK_
         }
     }
 
+    /// Number of tiles from `origin` to this tile, ignoring the wrap around the world
+    pub fn tiles_from(&self, origin: &Self) -> i64 {
+        let tile = |absolute: &Self| {
+            let Chunk { chunk_id, offset } = absolute.into_chunk();
+            i64::from(chunk_id) * MAX_OFFSET as i64 + i64::from(offset)
+        };
+
+        tile(self) - tile(origin)
+    }
+
     /// Adjust the chunk ID by `val`
     ///
     /// # Panics
@@ -772,6 +1185,14 @@ impl<const MAX_CHUNK_ID: usize, const MAX_OFFSET: usize> std::cmp::PartialOrd
     for AbsoluteTile<MAX_CHUNK_ID, MAX_OFFSET>
 {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const MAX_CHUNK_ID: usize, const MAX_OFFSET: usize> std::cmp::Ord
+    for AbsoluteTile<MAX_CHUNK_ID, MAX_OFFSET>
+{
+    fn cmp(&self, other: &Self) -> Ordering {
         let Chunk { chunk_id, offset } = self.into_chunk();
 
         let Chunk {
@@ -780,8 +1201,7 @@ impl<const MAX_CHUNK_ID: usize, const MAX_OFFSET: usize> std::cmp::PartialOrd
         } = other.into_chunk();
 
         // First try to compare chunk ids directly
-
-        let result = if chunk_id - other_chunk_id < other_chunk_id - chunk_id {
+        if chunk_id - other_chunk_id < other_chunk_id - chunk_id {
             Ordering::Less
         } else if chunk_id - other_chunk_id > other_chunk_id - chunk_id {
             Ordering::Greater
@@ -789,9 +1209,7 @@ impl<const MAX_CHUNK_ID: usize, const MAX_OFFSET: usize> std::cmp::PartialOrd
             offset.cmp(&other_offset)
         } else {
             unreachable!()
-        };
-
-        Some(result)
+        }
     }
 }
 
@@ -862,6 +1280,34 @@ impl WorldPosition {
         }
     }
 
+    /// Distance in meters from `origin` to this position, ignoring the floor
+    #[allow(clippy::cast_precision_loss)]
+    pub fn offset_from(&self, origin: &WorldPosition) -> Vector2<Meters> {
+        let tiles = Vector2::new(
+            self.tile_map_x.tiles_from(&origin.tile_map_x) as f32,
+            self.tile_map_y.tiles_from(&origin.tile_map_y) as f32,
+        );
+
+        Vector2::new(
+            TILE_SIDE_IN_METERS * Meters::new(tiles.x),
+            TILE_SIDE_IN_METERS * Meters::new(tiles.y),
+        ) + self.tile_rel
+            - origin.tile_rel
+    }
+
+    /// Get the position `t` of the way from this position to `to`. Jumps straight to `to`
+    /// if it is on a different floor.
+    pub fn lerp(&self, to: &WorldPosition, t: f32) -> WorldPosition {
+        if self.z != to.z {
+            return *to;
+        }
+
+        let mut position = *self;
+        position += to.offset_from(self) * Meters::new(t);
+        position.canonicalize();
+        position
+    }
+
     /// Return the `Vector2` of the (x, y) chunk coordinates
     pub fn into_chunk(&self) -> ChunkVector {
         let Chunk {
@@ -946,6 +1392,9 @@ pub enum Button {
 
     /// Increase player speed
     IncreaseSpeed,
+
+    /// Join the game as a new player
+    Start,
 }
 
 impl Button {
@@ -959,10 +1408,130 @@ impl Button {
             Button::Right,
             Button::DecreaseSpeed,
             Button::IncreaseSpeed,
+            Button::Start,
         ];
 
         VALS[val]
     }
+
+    /// Get a [`Button`] from its lowercase name, such as `up` or `increase_speed`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "up" => Some(Button::Up),
+            "down" => Some(Button::Down),
+            "left" => Some(Button::Left),
+            "right" => Some(Button::Right),
+            "decrease_speed" => Some(Button::DecreaseSpeed),
+            "increase_speed" => Some(Button::IncreaseSpeed),
+            "start" => Some(Button::Start),
+            _ => None,
+        }
+    }
+}
+
+/// State of a button over a single frame
+///
+/// Tracks the transitions during the frame as well as the final state, so a button
+/// that was pressed and released within one frame is still seen as pressed.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ButtonState {
+    /// Is the button down at the end of the frame
+    pub ended_down: bool,
+
+    /// Number of times the button went up or down during the frame
+    pub half_transition_count: u8,
+}
+
+impl ButtonState {
+    /// Returns true if the button is down at the end of the frame
+    pub fn is_down(self) -> bool {
+        self.ended_down
+    }
+
+    /// Returns true if the button went down at some point during the frame
+    pub fn was_pressed(self) -> bool {
+        self.half_transition_count > 1 || (self.half_transition_count == 1 && self.ended_down)
+    }
+
+    /// Returns true if the button went up at some point during the frame
+    pub fn was_released(self) -> bool {
+        self.half_transition_count > 1 || (self.half_transition_count == 1 && !self.ended_down)
+    }
+
+    /// Record the button going down or up. Repeats of the current state, such as
+    /// keyboard auto-repeat, are not transitions.
+    pub fn process(&mut self, is_down: bool) {
+        if self.ended_down != is_down {
+            self.ended_down = is_down;
+            self.half_transition_count = self.half_transition_count.saturating_add(1);
+        }
+    }
+
+    /// Begin a new frame, keeping the button held if it ended the last frame down
+    pub fn begin_frame(&mut self) {
+        self.half_transition_count = 0;
+    }
+}
+
+/// Buttons on the mouse
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MouseButton {
+    /// Left mouse button
+    Left = 0,
+
+    /// Middle mouse button, usually the wheel
+    Middle,
+
+    /// Right mouse button
+    Right,
+}
+
+/// State of the mouse over the game window
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Mouse {
+    /// Horizontal position of the cursor in framebuffer pixels. Can be outside of the
+    /// framebuffer while a button is held and dragged out of the window.
+    pub x: i32,
+
+    /// Vertical position of the cursor in framebuffer pixels. Can be outside of the
+    /// framebuffer while a button is held and dragged out of the window.
+    pub y: i32,
+
+    /// State of each mouse button over this frame
+    pub buttons: [ButtonState; variant_count::<MouseButton>()],
+
+    /// Number of wheel notches turned away from the user (negative towards the user)
+    /// since the last frame
+    pub wheel: i32,
+}
+
+impl Mouse {
+    /// Get the state of the given mouse `button`
+    pub fn button(&self, button: MouseButton) -> ButtonState {
+        self.buttons[button as usize]
+    }
+}
+
+/// State of a keyboard or gamepad over a single frame
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Controller {
+    /// Is the controller connected. A disconnected controller is centered with nothing
+    /// held.
+    pub is_connected: bool,
+
+    /// Position of the left stick outside of its deadzone, from -1.0 to 1.0 on each
+    /// axis with up and right positive
+    pub stick: Vector2<f32>,
+
+    /// State of each button over this frame
+    pub buttons: [ButtonState; variant_count::<Button>()],
+}
+
+impl Controller {
+    /// Get the state of the given `button`
+    pub fn button(&self, button: Button) -> ButtonState {
+        self.buttons[button as usize]
+    }
 }
 
 /// Color represented by red, green, blue pigments with alpha channel
@@ -1176,3 +1745,188 @@ pub enum PlayerDirection {
     /// Player is facing right
     Right,
 }
+
+/// Sound effects the game can play
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SoundEffect {
+    /// A player taking a step
+    Footstep,
+
+    /// A player running into a wall
+    Bump,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a chunk with the given `id` and `body`, padded to an even size
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut res = id.to_vec();
+        res.extend(u32::try_from(body.len()).unwrap().to_le_bytes());
+        res.extend(body);
+        if body.len() % 2 == 1 {
+            res.push(0);
+        }
+        res
+    }
+
+    /// Build the body of a `fmt ` chunk
+    fn format(format_tag: u16, channels: u16, rate: u32, block_align: u16, bits: u16) -> Vec<u8> {
+        let mut res = Vec::new();
+        res.extend(format_tag.to_le_bytes());
+        res.extend(channels.to_le_bytes());
+        res.extend(rate.to_le_bytes());
+        res.extend((rate * u32::from(block_align)).to_le_bytes());
+        res.extend(block_align.to_le_bytes());
+        res.extend(bits.to_le_bytes());
+        res
+    }
+
+    /// Build a RIFF/WAVE file holding the given `chunks`
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body = chunks.concat();
+        let mut res = b"RIFF".to_vec();
+        res.extend(u32::try_from(body.len() + 4).unwrap().to_le_bytes());
+        res.extend(b"WAVE");
+        res.extend(body);
+        res
+    }
+
+    /// Build a 16 bit PCM file with the given `channels` holding `samples`
+    fn pcm16(channels: u16, samples: &[i16]) -> Vec<u8> {
+        let data = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+        wav(&[
+            chunk(
+                b"fmt ",
+                &format(WAVE_FORMAT_PCM, channels, 48_000, channels * 2, 16),
+            ),
+            chunk(b"data", &data),
+        ])
+    }
+
+    /// Parse `data`, returning the error
+    fn parse_err(data: &[u8]) -> WavError {
+        SoundAsset::from_data(data).expect_err("parsed an invalid WAV file")
+    }
+
+    #[test]
+    fn mono_16_bit() {
+        let data = pcm16(1, &[0, 16384, -32768]);
+        let asset = SoundAsset::from_data(&data).unwrap();
+
+        assert_eq!(asset.samples_per_second, 48_000);
+        assert_eq!(asset.channels, 1);
+        assert_eq!(asset.bits_per_sample, 16);
+        assert_eq!(asset.frame_count(), 3);
+        assert_eq!(asset.sample(1, 0), 0.5);
+
+        // Mono plays the same sample on both channels, and past the end is silent
+        assert_eq!(asset.sample(2, 1), -1.0);
+        assert_eq!(asset.sample(3, 0), 0.0);
+    }
+
+    #[test]
+    fn stereo_16_bit() {
+        let data = pcm16(2, &[16384, -16384, 0, 8192]);
+        let asset = SoundAsset::from_data(&data).unwrap();
+
+        assert_eq!(asset.channels, 2);
+        assert_eq!(asset.frame_count(), 2);
+        assert_eq!(asset.sample(0, 0), 0.5);
+        assert_eq!(asset.sample(0, 1), -0.5);
+        assert_eq!(asset.sample(1, 1), 0.25);
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let data = wav(&[
+            chunk(b"LIST", b"odd"),
+            chunk(b"fmt ", &format(WAVE_FORMAT_PCM, 1, 22_050, 1, 8)),
+            chunk(b"data", &[128, 255]),
+        ]);
+        let asset = SoundAsset::from_data(&data).unwrap();
+
+        assert_eq!(asset.bits_per_sample, 8);
+        assert_eq!(asset.frame_count(), 2);
+        assert_eq!(asset.sample(0, 0), 0.0);
+    }
+
+    #[test]
+    fn not_wave() {
+        assert_eq!(parse_err(b""), WavError::NotWave);
+        assert_eq!(parse_err(b"RIFF\0\0\0\0AVI "), WavError::NotWave);
+    }
+
+    #[test]
+    fn truncated_chunk() {
+        let mut data = pcm16(1, &[1, 2, 3]);
+        data.truncate(data.len() - 1);
+        assert_eq!(parse_err(&data), WavError::TruncatedChunk(*b"data"));
+    }
+
+    #[test]
+    fn missing_chunk() {
+        let data = wav(&[chunk(b"data", &[0, 0])]);
+        assert_eq!(parse_err(&data), WavError::MissingChunk("fmt "));
+
+        let data = wav(&[chunk(b"fmt ", &format(WAVE_FORMAT_PCM, 1, 48_000, 2, 16))]);
+        assert_eq!(parse_err(&data), WavError::MissingChunk("data"));
+    }
+
+    #[test]
+    fn invalid_format_chunk() {
+        let data = wav(&[chunk(b"fmt ", &[1, 0, 1, 0]), chunk(b"data", &[0, 0])]);
+        assert_eq!(parse_err(&data), WavError::InvalidFormatChunk(4));
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let data = wav(&[
+            chunk(b"fmt ", &format(3, 1, 48_000, 4, 32)),
+            chunk(b"data", &[0; 4]),
+        ]);
+        assert_eq!(parse_err(&data), WavError::UnsupportedFormat(3));
+    }
+
+    #[test]
+    fn unsupported_bits_per_sample() {
+        let data = wav(&[
+            chunk(b"fmt ", &format(WAVE_FORMAT_PCM, 1, 48_000, 3, 24)),
+            chunk(b"data", &[0; 3]),
+        ]);
+        assert_eq!(parse_err(&data), WavError::UnsupportedBitsPerSample(24));
+    }
+
+    #[test]
+    fn unsupported_channels() {
+        let data = wav(&[
+            chunk(b"fmt ", &format(WAVE_FORMAT_PCM, 6, 48_000, 12, 16)),
+            chunk(b"data", &[0; 12]),
+        ]);
+        assert_eq!(parse_err(&data), WavError::UnsupportedChannels(6));
+    }
+
+    #[test]
+    fn invalid_sample_rate() {
+        for rate in [0, MAX_SOUND_SAMPLES_PER_SECOND + 1] {
+            let data = wav(&[
+                chunk(b"fmt ", &format(WAVE_FORMAT_PCM, 1, rate, 2, 16)),
+                chunk(b"data", &[0; 2]),
+            ]);
+            assert_eq!(parse_err(&data), WavError::InvalidSampleRate(rate));
+        }
+    }
+
+    #[test]
+    fn invalid_block_align() {
+        let data = wav(&[
+            chunk(b"fmt ", &format(WAVE_FORMAT_PCM, 2, 48_000, 2, 16)),
+            chunk(b"data", &[0; 4]),
+        ]);
+        assert_eq!(parse_err(&data), WavError::InvalidBlockAlign(2));
+    }
+}
@@ -68,6 +68,83 @@ pub struct Memory {
     pub next_allocation: usize,
 }
 
+/// Header stored at the start of the game memory identifying the layout of the root
+/// structure that follows it
+#[repr(C)]
+struct RootHeader {
+    /// The layout id of the root structure, from [`root_layout`]
+    layout: u64,
+}
+
+/// Offset of the root structure in the game memory, after the 16 byte aligned header
+const ROOT_INDEX: usize = (size_of::<RootHeader>() + 0xf) & !0xf;
+
+/// Get the layout id of a root structure `T` at the given `version`
+///
+/// The id covers the size and alignment of `T` and the sizes of the `dependents`
+/// allocated by `T`, so most layout changes are detected automatically. Changes that
+/// keep the sizes the same, such as reordering fields, need a new `version`.
+pub const fn root_layout<T>(version: u32, dependents: &[usize]) -> u64 {
+    let mut hash = crate::fnv1a(crate::FNV_OFFSET_BASIS, &version.to_le_bytes());
+    hash = crate::fnv1a(hash, &size_of::<T>().to_le_bytes());
+    hash = crate::fnv1a(hash, &core::mem::align_of::<T>().to_le_bytes());
+
+    let mut index = 0;
+    while index < dependents.len() {
+        hash = crate::fnv1a(hash, &dependents[index].to_le_bytes());
+        index += 1;
+    }
+
+    hash
+}
+
+/// Converts the game memory holding a root structure of one layout to another layout
+#[derive(Copy, Clone, Debug)]
+pub struct Migration {
+    /// The layout id this migration converts from
+    pub from: u64,
+
+    /// The layout id this migration converts to
+    pub to: u64,
+
+    /// Rewrite the root structure (and anything it allocated) in place from the `from`
+    /// layout to the `to` layout, reading it through [`Memory::root_unchecked`]
+    pub migrate: fn(&mut Memory),
+}
+
+/// How the root structure was found by [`Memory::root`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RootStatus {
+    /// The root structure already exists with the requested layout
+    Existing,
+
+    /// The memory was uninitialized and a zeroed root structure was allocated
+    Initialized,
+
+    /// The root structure had the `from` layout and was migrated to the requested layout
+    Migrated {
+        /// The layout id found in memory
+        from: u64,
+    },
+
+    /// The root structure had the `from` layout with no migration to the requested
+    /// layout, so the memory was reset and a zeroed root structure was allocated
+    Reinitialized {
+        /// The layout id found in memory
+        from: u64,
+    },
+}
+
+impl RootStatus {
+    /// Returns true if the root structure is zeroed and needs to be initialized
+    pub fn needs_init(self) -> bool {
+        matches!(
+            self,
+            RootStatus::Initialized | RootStatus::Reinitialized { .. }
+        )
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Allocation<T> {
     /// Index into the memory for this allocation
@@ -104,6 +181,12 @@ impl<T> core::ops::DerefMut for Allocation<T> {
     }
 }
 
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Memory {
     /// Allocate a new chunk of memory
     #[cfg(target_os = "linux")]
@@ -147,8 +230,279 @@ impl Memory {
         }
     }
 
+    /// Get the root structure `T` at the start of the game memory with the given
+    /// `layout` id from [`root_layout`]
+    ///
+    /// If the memory holds a root structure with a different layout, the `migrations`
+    /// are applied in order from the layout in memory to `layout`. If there is no path
+    /// of migrations, the memory is reset and a new root structure is allocated. Check
+    /// [`RootStatus::needs_init`] to know if the returned root structure is zeroed.
+    pub fn root<T>(
+        &mut self,
+        layout: u64,
+        migrations: &[Migration],
+    ) -> (Allocation<T>, RootStatus) {
+        if !self.initialized {
+            return (self.alloc_root(layout), RootStatus::Initialized);
+        }
+
+        // The header is always allocated first in initialized memory
+        let mut header = Allocation::<RootHeader> {
+            index: 0,
+            phantom: PhantomData,
+        };
+
+        let root = Allocation {
+            index: ROOT_INDEX,
+            phantom: PhantomData,
+        };
+
+        let from = header.layout;
+        if from == layout {
+            return (root, RootStatus::Existing);
+        }
+
+        // Each migration is used at most once to avoid cycles
+        let mut current = from;
+        for _ in 0..migrations.len() {
+            let Some(migration) = migrations.iter().find(|m| m.from == current) else {
+                break;
+            };
+
+            (migration.migrate)(self);
+            current = migration.to;
+            header.layout = current;
+
+            if current == layout {
+                return (root, RootStatus::Migrated { from });
+            }
+        }
+
+        // No way to migrate the old layout, start over with a new root structure
+        (self.alloc_root(layout), RootStatus::Reinitialized { from })
+    }
+
+    /// Get the root structure as `T` without checking its layout. Used by [`Migration`]s
+    /// to read the root structure in its old layout and rewrite it in the new one.
+    ///
+    /// # Panics
+    ///
+    /// * The memory has no root structure
+    pub fn root_unchecked<T>(&mut self) -> Allocation<T> {
+        assert!(self.initialized, "Game memory has no root structure");
+
+        Allocation {
+            index: ROOT_INDEX,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Reset the memory and allocate a zeroed root structure `T` with the given `layout`
+    fn alloc_root<T>(&mut self, layout: u64) -> Allocation<T> {
+        self.reset();
+
+        let mut header = self.alloc::<RootHeader>();
+        header.layout = layout;
+
+        let root = self.alloc::<T>();
+        assert_eq!(
+            root.index, ROOT_INDEX,
+            "Root structure must follow the header"
+        );
+
+        self.initialized = true;
+        root
+    }
+
+    /// Zero the game memory and mark it as uninitialized so the game sets it up again
+    pub fn reset(&mut self) {
+        // SAFETY: The game memory is always mapped for `MEMORY_LENGTH` bytes
+        unsafe {
+            std::ptr::write_bytes(MEMORY_BASE_ADDR as *mut u8, 0, MEMORY_LENGTH);
+        }
+
+        self.initialized = false;
+        self.next_allocation = 0;
+    }
+
+    /// Get the current data of the game memory
+    pub fn data(&self) -> &[u8] {
+        // SAFETY: The game memory is always mapped for `MEMORY_LENGTH` bytes
+        unsafe { std::slice::from_raw_parts(MEMORY_BASE_ADDR as *const u8, MEMORY_LENGTH) }
+    }
+
+    /// Overwrite the game memory with a previously taken snapshot of its `data`
+    ///
+    /// # Panics
+    ///
+    /// * `data` is not `MEMORY_LENGTH` in length
+    pub fn restore(&mut self, data: &[u8]) {
+        assert_eq!(data.len(), MEMORY_LENGTH, "Memory snapshot size mismatch");
+
+        // SAFETY: The game memory is always mapped for `MEMORY_LENGTH` bytes and the
+        //         snapshot was checked to be the same length
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                MEMORY_BASE_ADDR as *mut u8,
+                MEMORY_LENGTH,
+            );
+        }
+    }
+
     /// Create a copy of the current data as a Vec<u8>
     pub fn data_as_vec(&self) -> Vec<u8> {
         unsafe { std::slice::from_raw_parts(MEMORY_BASE_ADDR as *const u8, MEMORY_LENGTH).to_vec() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, PoisonError};
+
+    /// The game memory can only be mapped once per process, so tests take turns using it
+    static MEMORY: Mutex<Option<Memory>> = Mutex::new(None);
+
+    /// Run `test` with the game memory reset
+    fn with_memory(test: impl FnOnce(&mut Memory)) {
+        let mut memory = MEMORY.lock().unwrap_or_else(PoisonError::into_inner);
+        let memory = memory.get_or_insert_with(Memory::new);
+        memory.reset();
+        test(memory);
+    }
+
+    /// First layout of the test root structure, with the size in tiles
+    #[derive(Debug)]
+    struct RootV1 {
+        width: u32,
+        height: u32,
+    }
+
+    /// Second layout, with the fields swapped
+    #[derive(Debug)]
+    struct RootV2 {
+        height: u32,
+        width: u32,
+    }
+
+    /// Third layout, with the size in pixels
+    #[derive(Debug)]
+    struct RootV3 {
+        height_pixels: u32,
+        width_pixels: u32,
+    }
+
+    const LAYOUT_V1: u64 = root_layout::<RootV1>(1, &[]);
+    const LAYOUT_V2: u64 = root_layout::<RootV2>(2, &[]);
+    const LAYOUT_V3: u64 = root_layout::<RootV3>(3, &[]);
+
+    /// Pixels in each tile, converted by the `V2` to `V3` migration
+    const TILE_PIXELS: u32 = 16;
+
+    fn v1_to_v2(memory: &mut Memory) {
+        let old = memory.root_unchecked::<RootV1>();
+        let (width, height) = (old.width, old.height);
+
+        let mut new = memory.root_unchecked::<RootV2>();
+        new.width = width;
+        new.height = height;
+    }
+
+    fn v2_to_v3(memory: &mut Memory) {
+        let old = memory.root_unchecked::<RootV2>();
+        let (width, height) = (old.width, old.height);
+
+        let mut new = memory.root_unchecked::<RootV3>();
+        new.width_pixels = width * TILE_PIXELS;
+        new.height_pixels = height * TILE_PIXELS;
+    }
+
+    const MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: LAYOUT_V2,
+            to: LAYOUT_V3,
+            migrate: v2_to_v3,
+        },
+        Migration {
+            from: LAYOUT_V1,
+            to: LAYOUT_V2,
+            migrate: v1_to_v2,
+        },
+    ];
+
+    /// Create a `V1` root structure of 3x5 tiles
+    fn init_v1(memory: &mut Memory) {
+        let (mut root, status) = memory.root::<RootV1>(LAYOUT_V1, &[]);
+        assert_eq!(status, RootStatus::Initialized);
+        root.width = 3;
+        root.height = 5;
+    }
+
+    #[test]
+    fn root_exists_with_same_layout() {
+        with_memory(|memory| {
+            init_v1(memory);
+
+            let (root, status) = memory.root::<RootV1>(LAYOUT_V1, MIGRATIONS);
+            assert_eq!(status, RootStatus::Existing);
+            assert_eq!((root.width, root.height), (3, 5));
+        });
+    }
+
+    #[test]
+    fn root_migrates_through_each_layout() {
+        with_memory(|memory| {
+            init_v1(memory);
+
+            let (root, status) = memory.root::<RootV3>(LAYOUT_V3, MIGRATIONS);
+            assert_eq!(status, RootStatus::Migrated { from: LAYOUT_V1 });
+            assert!(!status.needs_init());
+            assert_eq!((root.width_pixels, root.height_pixels), (48, 80));
+
+            // The header now holds the new layout
+            let (_, status) = memory.root::<RootV3>(LAYOUT_V3, MIGRATIONS);
+            assert_eq!(status, RootStatus::Existing);
+        });
+    }
+
+    #[test]
+    fn root_reinitializes_without_migration() {
+        with_memory(|memory| {
+            init_v1(memory);
+
+            // Migrations from V1 only reach V2, never V3
+            let (root, status) = memory.root::<RootV3>(LAYOUT_V3, &MIGRATIONS[1..]);
+            assert_eq!(status, RootStatus::Reinitialized { from: LAYOUT_V1 });
+            assert!(status.needs_init());
+            assert_eq!((root.width_pixels, root.height_pixels), (0, 0));
+
+            let (_, status) = memory.root::<RootV3>(LAYOUT_V3, MIGRATIONS);
+            assert_eq!(status, RootStatus::Existing);
+        });
+    }
+
+    #[test]
+    fn root_reinitializes_unknown_layout() {
+        with_memory(|memory| {
+            init_v1(memory);
+
+            // Migrations going in a circle never reach the requested layout
+            let cycle = [
+                Migration {
+                    from: LAYOUT_V1,
+                    to: LAYOUT_V2,
+                    migrate: v1_to_v2,
+                },
+                Migration {
+                    from: LAYOUT_V2,
+                    to: LAYOUT_V1,
+                    migrate: |_| {},
+                },
+            ];
+
+            let (_, status) = memory.root::<RootV3>(LAYOUT_V3, &cycle);
+            assert!(matches!(status, RootStatus::Reinitialized { .. }));
+        });
+    }
+}
@@ -0,0 +1,454 @@
+//! Instrumented profiler timing blocks of code with the time stamp counter
+//!
+//! [`timed_block!`](crate::timed_block) records the cycles spent until the end of the
+//! enclosing block:
+//!
+//! ```ignore
+//! {
+//!     timed_block!("simulate");
+//!     simulate(game, state, world);
+//! }
+//! ```
+//!
+//! Records go to the current thread's [`Profiler`], set with [`set_profiler`]. The
+//! platform and the hot reloaded game library each have their own copy of this module,
+//! so the platform sets its profiler at startup and passes it to the game through
+//! [`Game`](crate::Game), which the game sets as its own on every call. At the end of
+//! each frame the platform collects the records into a [`Session`], which can be written
+//! out as a Chrome trace.
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Number of records kept each frame. Records past this are dropped.
+pub const MAX_RECORDS_PER_FRAME: usize = 4096;
+
+/// Read the time stamp counter
+#[inline]
+pub fn read_counter() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+thread_local! {
+    /// The profiler timed blocks on this thread are recorded into
+    static PROFILER: Cell<Option<&'static Profiler>> = const { Cell::new(None) };
+}
+
+/// Record the timed blocks on this thread into `profiler`, or stop recording with `None`
+pub fn set_profiler(profiler: Option<&'static Profiler>) {
+    PROFILER.with(|current| current.set(profiler));
+}
+
+/// A timed block recorded by a [`Profiler`]
+#[derive(Debug, Copy, Clone)]
+struct Record {
+    /// Name given to the block. Only valid until the game library is reloaded, so names
+    /// are copied out by [`Session::collect`] every frame.
+    name: &'static str,
+
+    /// Counter when the block began
+    start: u64,
+
+    /// Counter when the block ended
+    end: u64,
+
+    /// Number of timed blocks the block is nested in
+    depth: u32,
+}
+
+impl Record {
+    /// An unused record slot
+    const EMPTY: Record = Record {
+        name: "",
+        start: 0,
+        end: 0,
+        depth: 0,
+    };
+}
+
+/// Collects the timed blocks run over a frame
+#[derive(Debug)]
+pub struct Profiler {
+    /// Blocks finished this frame
+    records: [Cell<Record>; MAX_RECORDS_PER_FRAME],
+
+    /// Number of records used this frame
+    count: Cell<usize>,
+
+    /// Number of timed blocks currently running
+    depth: Cell<u32>,
+
+    /// Number of records dropped this frame because `records` was full
+    dropped: Cell<usize>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            records: [const { Cell::new(Record::EMPTY) }; MAX_RECORDS_PER_FRAME],
+            count: Cell::new(0),
+            depth: Cell::new(0),
+            dropped: Cell::new(0),
+        }
+    }
+}
+
+impl Profiler {
+    /// Create a [`Profiler`] that lives for the rest of the program, as needed to pass it
+    /// to [`set_profiler`]
+    pub fn leak() -> &'static Profiler {
+        Box::leak(Box::default())
+    }
+
+    /// Number of records taken this frame
+    pub fn len(&self) -> usize {
+        self.count.get()
+    }
+
+    /// Returns true if no blocks have been recorded this frame
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget the records taken this frame
+    pub fn clear(&self) {
+        self.count.set(0);
+        self.dropped.set(0);
+    }
+
+    /// Add a finished block to this frame's records
+    fn record(&self, record: Record) {
+        let count = self.count.get();
+        match self.records.get(count) {
+            Some(slot) => {
+                slot.set(record);
+                self.count.set(count + 1);
+            }
+            None => self.dropped.set(self.dropped.get() + 1),
+        }
+    }
+}
+
+/// Records the time from its creation until it is dropped. Created by
+/// [`timed_block!`](crate::timed_block).
+#[derive(Debug)]
+pub struct TimedBlock {
+    /// Name of the block
+    name: &'static str,
+
+    /// Counter when the block began
+    start: u64,
+
+    /// Number of timed blocks this block is nested in
+    depth: u32,
+
+    /// Profiler to record the block into, if one is set
+    profiler: Option<&'static Profiler>,
+}
+
+impl TimedBlock {
+    /// Begin timing the block called `name`
+    #[inline]
+    pub fn begin(name: &'static str) -> Self {
+        let profiler = PROFILER.with(Cell::get);
+
+        let depth = profiler.map_or(0, |profiler| {
+            let depth = profiler.depth.get();
+            profiler.depth.set(depth + 1);
+            depth
+        });
+
+        Self {
+            name,
+            start: read_counter(),
+            depth,
+            profiler,
+        }
+    }
+}
+
+impl Drop for TimedBlock {
+    #[inline]
+    fn drop(&mut self) {
+        let end = read_counter();
+
+        if let Some(profiler) = self.profiler {
+            profiler.depth.set(self.depth);
+            profiler.record(Record {
+                name: self.name,
+                start: self.start,
+                end,
+                depth: self.depth,
+            });
+        }
+    }
+}
+
+/// Time the rest of the enclosing block under the given name
+#[macro_export]
+macro_rules! timed_block {
+    ($name:expr) => {
+        let _timed_block = $crate::profile::TimedBlock::begin($name);
+    };
+}
+
+/// A timed block collected into a [`Session`]
+#[derive(Debug)]
+struct Event {
+    /// Index of the name in [`Session::names`]
+    name: usize,
+
+    /// Frame the block ran in
+    frame: u64,
+
+    /// Counter when the block began
+    start: u64,
+
+    /// Counter when the block ended
+    end: u64,
+
+    /// Number of timed blocks the block is nested in
+    depth: u32,
+}
+
+/// The timed blocks of every frame over a profiling session
+#[derive(Debug)]
+pub struct Session {
+    /// Counter when the session began
+    start_counter: u64,
+
+    /// Time when the session began, used to convert counters into time
+    start_time: Instant,
+
+    /// Names of the timed blocks
+    names: Vec<String>,
+
+    /// Every collected block
+    events: Vec<Event>,
+
+    /// The next frame to be collected
+    frame: u64,
+
+    /// Number of records dropped over the session
+    dropped: usize,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    /// Begin a new session
+    pub fn new() -> Self {
+        Self {
+            start_counter: read_counter(),
+            start_time: Instant::now(),
+            names: Vec::new(),
+            events: Vec::new(),
+            frame: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Number of records dropped over the session because a frame ran out of records
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Move the records of the frame from `profiler` into the session. Must be called
+    /// before the game library is reloaded, since the records borrow its block names.
+    pub fn collect(&mut self, profiler: &Profiler) {
+        for record in &profiler.records[..profiler.len()] {
+            let record = record.get();
+
+            let name = match self.names.iter().position(|name| name == record.name) {
+                Some(index) => index,
+                None => {
+                    self.names.push(record.name.to_string());
+                    self.names.len() - 1
+                }
+            };
+
+            self.events.push(Event {
+                name,
+                frame: self.frame,
+                start: record.start,
+                end: record.end,
+                depth: record.depth,
+            });
+        }
+
+        self.dropped += profiler.dropped.get();
+        self.frame += 1;
+        profiler.clear();
+    }
+
+    /// Write the session as a Chrome trace into the file at `path`
+    ///
+    /// # Errors
+    ///
+    /// * Failed to create or write the file
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_chrome_trace(&mut file)?;
+        file.flush()
+    }
+
+    /// Write the session as Chrome trace event JSON, viewable in `chrome://tracing` or
+    /// Perfetto
+    ///
+    /// # Errors
+    ///
+    /// * Failed to write to `out`
+    #[allow(clippy::cast_precision_loss)]
+    pub fn write_chrome_trace(&self, out: &mut impl Write) -> std::io::Result<()> {
+        // Measure the counter rate over the whole session to convert counters into time
+        let elapsed = self.start_time.elapsed().as_secs_f64() * 1_000_000.0;
+        let counters = read_counter().wrapping_sub(self.start_counter) as f64;
+        let counters_per_us = if elapsed > 0.0 {
+            counters / elapsed
+        } else {
+            1.0
+        };
+
+        let to_us =
+            |counter: u64| counter.wrapping_sub(self.start_counter) as f64 / counters_per_us;
+
+        writeln!(out, "{{\"traceEvents\": [")?;
+
+        for (index, event) in self.events.iter().enumerate() {
+            let separator = if index + 1 < self.events.len() {
+                ","
+            } else {
+                ""
+            };
+
+            writeln!(
+                out,
+                "  {{\"name\": \"{}\", \"ph\": \"X\", \"pid\": 1, \"tid\": 1, \"ts\": {:.3}, \
+                 \"dur\": {:.3}, \"args\": {{\"frame\": {}, \"depth\": {}}}}}{separator}",
+                escape_json(&self.names[event.name]),
+                to_us(event.start),
+                (event.end.wrapping_sub(event.start)) as f64 / counters_per_us,
+                event.frame,
+                event.depth,
+            )?;
+        }
+
+        writeln!(out, "], \"displayTimeUnit\": \"ms\"}}")
+    }
+}
+
+/// Escape `text` to be placed in a JSON string
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for chr in text.chars() {
+        match chr {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            chr if chr.is_control() => escaped.push_str(&format!("\\u{:04x}", u32::from(chr))),
+            chr => escaped.push(chr),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// Names given to the test blocks, needing every kind of escape
+    const QUOTED: &str = "say \"hi\"";
+    const SLASHED: &str = "C:\\blocks";
+    const CONTROL: &str = "tab\tbell\u{7}";
+
+    /// Run the nested test blocks for a frame
+    fn run_frame() {
+        timed_block!("frame");
+        {
+            timed_block!(QUOTED);
+            {
+                timed_block!(SLASHED);
+            }
+        }
+        {
+            timed_block!(CONTROL);
+        }
+    }
+
+    /// Get the `key` field of a trace event as a float
+    fn float(event: &Value, key: &str) -> f64 {
+        event[key].as_f64().unwrap()
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let profiler = Profiler::leak();
+        set_profiler(Some(profiler));
+
+        let mut session = Session::new();
+        for _ in 0..2 {
+            run_frame();
+            session.collect(profiler);
+        }
+        set_profiler(None);
+
+        assert!(profiler.is_empty());
+        assert_eq!(session.dropped(), 0);
+
+        let mut out = Vec::new();
+        session.write_chrome_trace(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        // Names are escaped rather than breaking out of their strings
+        assert!(text.contains(r#""name": "say \"hi\"""#), "{text}");
+        assert!(text.contains(r#""name": "C:\\blocks""#), "{text}");
+        assert!(text.contains(r#""name": "tab\u0009bell\u0007""#), "{text}");
+
+        let trace: Value = serde_json::from_str(&text).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+
+        // Blocks are recorded as they finish, so inner blocks come before their parents
+        let expected = [(SLASHED, 2), (QUOTED, 1), (CONTROL, 1), ("frame", 0)];
+        assert_eq!(events.len(), expected.len() * 2);
+
+        for (index, event) in events.iter().enumerate() {
+            let (name, depth) = expected[index % expected.len()];
+            assert_eq!(event["name"], name);
+            assert_eq!(event["ph"], "X");
+            assert_eq!(event["args"]["depth"], depth);
+            assert_eq!(event["args"]["frame"], index / expected.len());
+        }
+
+        // Each block lies within its parent, allowing for the rounding of the times, and
+        // the blocks of the second frame follow the first
+        let contains = |outer: &Value, inner: &Value| {
+            float(inner, "ts") + 0.001 >= float(outer, "ts")
+                && float(inner, "ts") + float(inner, "dur")
+                    <= float(outer, "ts") + float(outer, "dur") + 0.002
+        };
+
+        for frame in events.chunks(expected.len()) {
+            let [slashed, quoted, control, whole] = frame else {
+                unreachable!();
+            };
+
+            assert!(contains(quoted, slashed));
+            assert!(contains(whole, quoted));
+            assert!(contains(whole, control));
+            assert!(float(control, "ts") + 0.001 >= float(quoted, "ts") + float(quoted, "dur"));
+        }
+
+        assert!(
+            float(&events[4], "ts") + 0.001 >= float(&events[3], "ts") + float(&events[3], "dur")
+        );
+    }
+}
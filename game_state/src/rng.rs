@@ -1,7 +1,9 @@
 //! Implementation of <https://github.com/eqv/rand_romu>
 
+use serde::{Deserialize, Serialize};
+
 /// Implementation of `RandRomu`
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rng {
     /// X state
     xstate: u64,
@@ -19,8 +21,21 @@ impl Default for Rng {
 impl Rng {
     /// Create a new [`Rng`] seeded by a number from a `Lehmer64` rng
     pub fn new() -> Rng {
-        // Generate the random state from Lehmer64
-        let mut lehmer64 = Lehmer64::new();
+        Self::from_lehmer64(Lehmer64::new())
+    }
+
+    /// Create a new [`Rng`] that always produces the same sequence for the same `seed`
+    pub fn from_seed(seed: u64) -> Rng {
+        Self::from_lehmer64(Lehmer64::from_seed(seed))
+    }
+
+    /// Get a new seed from `rdtsc` for when no seed was requested
+    pub fn random_seed() -> u64 {
+        Lehmer64::new().next()
+    }
+
+    /// Create a new [`Rng`] with the state generated from the given [`Lehmer64`]
+    fn from_lehmer64(mut lehmer64: Lehmer64) -> Rng {
         let mut res = Rng {
             xstate: lehmer64.next(),
             ystate: lehmer64.next(),
@@ -54,8 +69,14 @@ pub struct Lehmer64 {
 impl Lehmer64 {
     /// Create a new [`Lehmer64`] rng seeded by `rdtsc`
     pub fn new() -> Lehmer64 {
+        Self::from_seed(unsafe { core::arch::x86_64::_rdtsc() })
+    }
+
+    /// Create a new [`Lehmer64`] rng from the given `seed`
+    pub fn from_seed(seed: u64) -> Lehmer64 {
+        // The state is forced odd since a zero state would only ever produce zeros
         let mut res = Lehmer64 {
-            value: u128::from(unsafe { core::arch::x86_64::_rdtsc() }),
+            value: u128::from(seed) << 1 | 1,
         };
 
         // Cycle through to create some chaos
@@ -0,0 +1,180 @@
+//! Command line options for the linux platform
+
+use std::path::PathBuf;
+
+use game_state::Rng;
+use x11_rs::Scaling;
+
+use crate::{bindings, dl};
+
+/// Usage string printed when the command line fails to parse
+pub const USAGE: &str = "\
+usage: handmade_ferris [options]
+
+options:
+    --seed <seed>       Seed used to generate the world (decimal or 0x hex)
+    --headless          Run without an X11 window
+    --script <file>     Scripted input for a headless run
+    --frames <count>    Number of frames to run in headless mode
+    --output <dir>      Directory to write captured frames into (default: .)
+    --golden <dir>      Compare the scenarios in <dir> against their reference images
+    --bless             Rewrite the golden reference images instead of comparing
+    --tolerance <n>     Allowed per channel difference for golden images (default: 2)
+    --libgame <path>    Game logic library to load and hot reload
+                        (default: ./target/release/libgame.so)
+    --bindings <path>   Input bindings file
+                        (default: ./linux_platform/bindings.cfg)
+    --gamepad <file>    Replay a gamepad recorded by evemu-record instead of using
+                        the gamepads in /dev/input. Repeat for more gamepads.
+    --wav <file>        Write the game sound into a WAV file instead of playing it
+    --profile <file>    Record the timed blocks of every frame and write them into
+                        <file> as a Chrome trace on exit
+    --scale <mode>      Scale the game into a resized window with `stretch` or
+                        `integer` (default: stretch)
+    --no-shm            Copy frames to the X server instead of sharing memory with it
+    --help              Print this message";
+
+/// Options parsed from the command line
+#[derive(Debug)]
+pub struct Options {
+    /// Seed used to generate the world. A random seed is used if not given.
+    pub seed: Option<u64>,
+
+    /// Run the game without an X11 window
+    pub headless: bool,
+
+    /// Scripted input used to drive a headless run
+    pub script: Option<PathBuf>,
+
+    /// Number of frames to run in headless mode. Defaults to the length of the script.
+    pub frames: Option<u64>,
+
+    /// Directory to write captured framebuffers into
+    pub output_dir: PathBuf,
+
+    /// Directory of golden image scenarios to check
+    pub golden: Option<PathBuf>,
+
+    /// Rewrite the golden reference images rather than comparing against them
+    pub bless: bool,
+
+    /// Allowed difference in each color channel when comparing golden images
+    pub tolerance: u8,
+
+    /// Path of the game logic library
+    pub libgame: PathBuf,
+
+    /// Path of the input bindings file
+    pub bindings: PathBuf,
+
+    /// Recorded gamepads replayed in place of real gamepads
+    pub gamepads: Vec<PathBuf>,
+
+    /// WAV file the game sound is written into instead of being played
+    pub wav: Option<PathBuf>,
+
+    /// File the profiled timed blocks are written into as a Chrome trace
+    pub profile: Option<PathBuf>,
+
+    /// How the game is scaled into a resized window
+    pub scaling: Scaling,
+
+    /// Present frames through MIT-SHM when the X server supports it
+    pub shared_memory: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            headless: false,
+            script: None,
+            frames: None,
+            output_dir: PathBuf::from("."),
+            golden: None,
+            bless: false,
+            tolerance: 2,
+            libgame: PathBuf::from(dl::LIBGAME),
+            bindings: PathBuf::from(bindings::BINDINGS),
+            gamepads: Vec::new(),
+            wav: None,
+            profile: None,
+            scaling: Scaling::Stretch,
+            shared_memory: true,
+        }
+    }
+}
+
+impl Options {
+    /// Get the requested world seed or a new random one
+    pub fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(Rng::random_seed)
+    }
+
+    /// Parse the [`Options`] from the process arguments
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parse the [`Options`] from the given arguments
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Get the value for options that require one
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{name} requires a value"))
+            };
+
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = value("--seed")?;
+                    let parsed = match seed.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16),
+                        None => seed.parse(),
+                    };
+
+                    options.seed = Some(parsed.map_err(|_| format!("Invalid seed: {seed}"))?);
+                }
+                "--headless" => options.headless = true,
+                "--script" => options.script = Some(PathBuf::from(value("--script")?)),
+                "--output" => options.output_dir = PathBuf::from(value("--output")?),
+                "--golden" => options.golden = Some(PathBuf::from(value("--golden")?)),
+                "--bless" => options.bless = true,
+                "--no-shm" => options.shared_memory = false,
+                "--bindings" => options.bindings = PathBuf::from(value("--bindings")?),
+                "--gamepad" => options.gamepads.push(PathBuf::from(value("--gamepad")?)),
+                "--wav" => options.wav = Some(PathBuf::from(value("--wav")?)),
+                "--profile" => options.profile = Some(PathBuf::from(value("--profile")?)),
+                "--libgame" => options.libgame = PathBuf::from(value("--libgame")?),
+                "--scale" => {
+                    options.scaling = match value("--scale")?.as_str() {
+                        "stretch" => Scaling::Stretch,
+                        "integer" => Scaling::Integer,
+                        mode => return Err(format!("Invalid scale mode: {mode}")),
+                    };
+                }
+                "--frames" => {
+                    let frames = value("--frames")?;
+                    let frames = frames
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count: {frames}"))?;
+                    options.frames = Some(frames);
+                }
+                "--tolerance" => {
+                    let tolerance = value("--tolerance")?;
+                    let tolerance = tolerance
+                        .parse()
+                        .map_err(|_| format!("Invalid tolerance: {tolerance}"))?;
+                    options.tolerance = tolerance;
+                }
+                "--help" | "-h" => return Err(String::new()),
+                _ => return Err(format!("Unknown option: {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
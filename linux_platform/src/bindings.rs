@@ -0,0 +1,544 @@
+//! Action map binding keys and mouse buttons to game buttons and platform actions
+//!
+//! Bindings are grouped into [`Context`]s that can be turned on and off at runtime and
+//! are loaded from a bindings file at startup (see `linux_platform/bindings.cfg`). Each
+//! line of the file is a context, an input, and the action it triggers:
+//!
+//! ```text
+//! # context  input      action
+//! gameplay   w          up
+//! gameplay   alt+enter  fullscreen
+//! debug      1          loop_slot 1
+//! ```
+//!
+//! `#` starts a comment anywhere on a line, so the `#` key is bound by the name `hash`.
+//! An input can only be bound once in each context.
+
+use core::mem::variant_count;
+use std::path::{Path, PathBuf};
+
+use game_state::Button;
+use x11_rs::{Key, Modifiers, MouseButton};
+
+use crate::looping;
+
+/// The default bindings file, relative to the repository root
+pub const BINDINGS: &str = "./linux_platform/bindings.cfg";
+
+/// Bindings used when the default bindings file can't be found
+const DEFAULT_BINDINGS: &str = include_str!("../bindings.cfg");
+
+/// Errors that can occur while loading the bindings
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read the bindings file
+    Read(PathBuf, std::io::Error),
+
+    /// A line of the bindings file failed to parse
+    InvalidBinding {
+        /// Line number (starting at 1) of the bad line
+        line: usize,
+
+        /// Why the line is invalid
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Read(path, err) => {
+                write!(f, "failed to read bindings {}: {err}", path.display())
+            }
+            Error::InvalidBinding { line, reason } => {
+                write!(f, "bindings line {line}: {reason}")
+            }
+        }
+    }
+}
+
+/// Custom [`Result`] type for loading the bindings
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A group of bindings that is turned on and off together
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Context {
+    /// Playing the game
+    Gameplay = 0,
+
+    /// Debug tools such as looped input
+    Debug,
+
+    /// Editing the world
+    Editor,
+}
+
+impl Context {
+    /// Active contexts are searched in this order, so earlier contexts hide the
+    /// bindings of later ones
+    const PRIORITY: [Context; variant_count::<Context>()] =
+        [Context::Editor, Context::Debug, Context::Gameplay];
+
+    /// Get a [`Context`] from its lowercase name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gameplay" => Some(Context::Gameplay),
+            "debug" => Some(Context::Debug),
+            "editor" => Some(Context::Editor),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Context::Gameplay => write!(f, "gameplay"),
+            Context::Debug => write!(f, "debug"),
+            Context::Editor => write!(f, "editor"),
+        }
+    }
+}
+
+/// What a binding does
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Hold a game button while the input is held
+    Button(Button),
+
+    /// Select the loop slot for the next recording or playback
+    LoopSlot(usize),
+
+    /// Start recording, then play back the recording, then stop
+    LoopRecord,
+
+    /// Play back the recording in the selected slot
+    LoopPlay,
+
+    /// Toggle fullscreen
+    Fullscreen,
+
+    /// Turn a context on or off
+    Toggle(Context),
+}
+
+/// A key or mouse button
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// A key on the keyboard
+    Key(Key),
+
+    /// A button on the mouse
+    Mouse(MouseButton),
+}
+
+/// An input bound to an action in a context
+#[derive(Debug, Copy, Clone)]
+struct Binding {
+    /// The context the binding belongs to
+    context: Context,
+
+    /// The bound input
+    input: Input,
+
+    /// Modifiers that must be held for the binding to trigger
+    modifiers: Modifiers,
+
+    /// What the binding does
+    action: Action,
+}
+
+impl Binding {
+    /// Returns true if this binding is triggered by `input` with `held` modifiers
+    fn matches(&self, input: Input, held: Modifiers) -> bool {
+        self.input == input
+            && (!self.modifiers.shift || held.shift)
+            && (!self.modifiers.control || held.control)
+            && (!self.modifiers.alt || held.alt)
+            && (!self.modifiers.super_key || held.super_key)
+    }
+
+    /// Number of modifiers required by this binding, used to prefer `alt+enter` over
+    /// `enter` while Alt is held
+    fn modifier_count(&self) -> usize {
+        let modifiers = self.modifiers;
+        [
+            modifiers.shift,
+            modifiers.control,
+            modifiers.alt,
+            modifiers.super_key,
+        ]
+        .into_iter()
+        .filter(|held| *held)
+        .count()
+    }
+}
+
+/// Bindings from inputs to actions, grouped by context
+#[derive(Debug)]
+pub struct ActionMap {
+    /// Every binding in every context
+    bindings: Vec<Binding>,
+
+    /// Is each context currently active
+    active: [bool; variant_count::<Context>()],
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self::parse(DEFAULT_BINDINGS).expect("Invalid default bindings")
+    }
+}
+
+impl ActionMap {
+    /// Load the bindings at `path`. The built in bindings are used if `path` is the
+    /// default bindings file and it doesn't exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err)
+                if err.kind() == std::io::ErrorKind::NotFound && path == Path::new(BINDINGS) =>
+            {
+                Ok(Self::default())
+            }
+            Err(err) => Err(Error::Read(path.to_path_buf(), err)),
+        }
+    }
+
+    /// Parse an [`ActionMap`] from the text of a bindings file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut bindings: Vec<Binding> = Vec::new();
+
+        // Line number of each binding, to point at the first of two duplicate bindings
+        let mut lines = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: String| Error::InvalidBinding {
+                line: index + 1,
+                reason,
+            };
+
+            // Ignore comments and empty lines
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let context = words.next().unwrap_or_default();
+            let context = Context::from_name(context)
+                .ok_or_else(|| invalid(format!("unknown context: {context}")))?;
+
+            let Some(name) = words.next() else {
+                return Err(invalid("missing input".to_string()));
+            };
+            let (input, modifiers) =
+                parse_input(name).ok_or_else(|| invalid(format!("unknown input: {name}")))?;
+
+            // A second binding would be hidden by the first one
+            let duplicate = bindings.iter().zip(&lines).find(|(binding, _)| {
+                binding.context == context
+                    && binding.input == input
+                    && binding.modifiers == modifiers
+            });
+            if let Some((_, line)) = duplicate {
+                return Err(invalid(format!(
+                    "{name} is already bound in {context} on line {line}"
+                )));
+            }
+
+            let Some(action) = words.next() else {
+                return Err(invalid("missing action".to_string()));
+            };
+            let argument = words.next();
+
+            let action = match (action, argument) {
+                ("loop_slot", Some(slot)) => match slot.parse() {
+                    Ok(slot @ 1..=looping::SLOT_COUNT) => Action::LoopSlot(slot),
+                    _ => return Err(invalid(format!("invalid loop slot: {slot}"))),
+                },
+                ("toggle", Some(context)) => Action::Toggle(
+                    Context::from_name(context)
+                        .ok_or_else(|| invalid(format!("unknown context: {context}")))?,
+                ),
+                ("loop_slot" | "toggle", None) => {
+                    return Err(invalid(format!("{action} requires an argument")))
+                }
+                ("loop_record", None) => Action::LoopRecord,
+                ("loop_play", None) => Action::LoopPlay,
+                ("fullscreen", None) => Action::Fullscreen,
+                (_, None) => Action::Button(
+                    Button::from_name(action)
+                        .ok_or_else(|| invalid(format!("unknown action: {action}")))?,
+                ),
+                (_, Some(argument)) => {
+                    return Err(invalid(format!("unexpected argument: {argument}")))
+                }
+            };
+
+            if let Some(extra) = words.next() {
+                return Err(invalid(format!("unexpected argument: {extra}")));
+            }
+
+            bindings.push(Binding {
+                context,
+                input,
+                modifiers,
+                action,
+            });
+            lines.push(index + 1);
+        }
+
+        let mut active = [false; variant_count::<Context>()];
+        active[Context::Gameplay as usize] = true;
+        active[Context::Debug as usize] = true;
+
+        Ok(Self { bindings, active })
+    }
+
+    /// Returns true if the given `context` is active
+    pub fn is_active(&self, context: Context) -> bool {
+        self.active[context as usize]
+    }
+
+    /// Turn the given `context` on or off
+    pub fn toggle(&mut self, context: Context) {
+        self.active[context as usize] = !self.active[context as usize];
+    }
+
+    /// Get the action bound to `input` with the `held` modifiers in the active contexts
+    pub fn action(&self, input: Input, held: Modifiers) -> Option<Action> {
+        Context::PRIORITY
+            .into_iter()
+            .filter(|context| self.is_active(*context))
+            .find_map(|context| {
+                self.bindings
+                    .iter()
+                    .filter(|binding| binding.context == context && binding.matches(input, held))
+                    .max_by_key(|binding| binding.modifier_count())
+            })
+            .map(|binding| binding.action)
+    }
+
+    /// Get every game button bound to `input` in any context, regardless of modifiers.
+    /// Used to release buttons even if the modifiers or contexts changed while held.
+    pub fn buttons(&self, input: Input) -> impl Iterator<Item = Button> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |binding| binding.input == input)
+            .filter_map(|binding| match binding.action {
+                Action::Button(button) => Some(button),
+                _ => None,
+            })
+    }
+}
+
+/// Parse an input name with optional modifier prefixes, such as `w` or `alt+enter`
+fn parse_input(name: &str) -> Option<(Input, Modifiers)> {
+    let mut modifiers = Modifiers::default();
+    let mut name = name;
+
+    while let Some((modifier, rest)) = name.split_once('+').filter(|(_, rest)| !rest.is_empty()) {
+        match modifier {
+            "shift" => modifiers.shift = true,
+            "ctrl" => modifiers.control = true,
+            "alt" => modifiers.alt = true,
+            "super" => modifiers.super_key = true,
+            _ => return None,
+        }
+
+        name = rest;
+    }
+
+    let input = match name {
+        "mouse_left" => Input::Mouse(MouseButton::Left),
+        "mouse_middle" => Input::Mouse(MouseButton::Middle),
+        "mouse_right" => Input::Mouse(MouseButton::Right),
+        "mouse_back" => Input::Mouse(MouseButton::Back),
+        "mouse_forward" => Input::Mouse(MouseButton::Forward),
+        _ => Input::Key(parse_key(name)?),
+    };
+
+    Some((input, modifiers))
+}
+
+/// Parse a key name, either a single character or a named key such as `space`
+fn parse_key(name: &str) -> Option<Key> {
+    let key = match name {
+        "space" => Key::Space,
+        "hash" => Key::Char('#'),
+        "enter" => Key::Enter,
+        "escape" => Key::Escape,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "page_up" => Key::PageUp,
+        "page_down" => Key::PageDown,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "shift" => Key::Shift,
+        "control" => Key::Control,
+        "alt" => Key::Alt,
+        "super" => Key::Super,
+        "caps_lock" => Key::CapsLock,
+        _ => {
+            // Function keys `f1` through `f35`
+            if let Some(number) = name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                return (1..=35).contains(&number).then_some(Key::F(number));
+            }
+
+            // Keys are reported without Shift applied, so bind the lowercase character
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(chr), None) => Key::Char(chr.to_lowercase().next().unwrap_or(chr)),
+                _ => return None,
+            }
+        }
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `text`, returning the line and reason of the error
+    fn parse_err(text: &str) -> (usize, String) {
+        match ActionMap::parse(text) {
+            Err(Error::InvalidBinding { line, reason }) => (line, reason),
+            res => panic!("expected an invalid binding, got {res:?}"),
+        }
+    }
+
+    /// Get the action bound to the key for `chr` without modifiers
+    fn char_action(map: &ActionMap, chr: char) -> Option<Action> {
+        map.action(Input::Key(Key::Char(chr)), Modifiers::default())
+    }
+
+    #[test]
+    fn default_bindings() {
+        let map = ActionMap::default();
+        assert_eq!(char_action(&map, 'w'), Some(Action::Button(Button::Up)));
+        assert_eq!(char_action(&map, '3'), Some(Action::LoopSlot(3)));
+        assert_eq!(
+            map.action(Input::Key(Key::F(2)), Modifiers::default()),
+            Some(Action::Toggle(Context::Editor))
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let map = ActionMap::parse("# header\n\n  gameplay w up # trailing\n").unwrap();
+        assert_eq!(map.bindings.len(), 1);
+        assert_eq!(char_action(&map, 'w'), Some(Action::Button(Button::Up)));
+    }
+
+    #[test]
+    fn hash_key() {
+        let map = ActionMap::parse("gameplay hash start").unwrap();
+        assert_eq!(char_action(&map, '#'), Some(Action::Button(Button::Start)));
+    }
+
+    #[test]
+    fn uppercase_keys_bind_lowercase() {
+        let map = ActionMap::parse("gameplay W up").unwrap();
+        assert_eq!(char_action(&map, 'w'), Some(Action::Button(Button::Up)));
+    }
+
+    #[test]
+    fn unknown_context() {
+        assert_eq!(
+            parse_err("gameplay w up\nmenu w up"),
+            (2, "unknown context: menu".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_inputs() {
+        assert_eq!(parse_err("gameplay ww up").1, "unknown input: ww");
+        assert_eq!(parse_err("gameplay f36 up").1, "unknown input: f36");
+        assert_eq!(parse_err("gameplay hyper+w up").1, "unknown input: hyper+w");
+        assert_eq!(parse_err("gameplay").1, "missing input");
+    }
+
+    #[test]
+    fn unknown_actions() {
+        assert_eq!(parse_err("gameplay w jump").1, "unknown action: jump");
+        assert_eq!(parse_err("gameplay w").1, "missing action");
+        assert_eq!(parse_err("gameplay w up now").1, "unexpected argument: now");
+        assert_eq!(
+            parse_err("gameplay w toggle").1,
+            "toggle requires an argument"
+        );
+        assert_eq!(
+            parse_err("gameplay w toggle menu").1,
+            "unknown context: menu"
+        );
+        assert_eq!(parse_err("debug 5 loop_slot 5").1, "invalid loop slot: 5");
+        assert_eq!(
+            parse_err("debug 1 loop_slot 1 2").1,
+            "unexpected argument: 2"
+        );
+    }
+
+    #[test]
+    fn duplicate_bindings() {
+        assert_eq!(
+            parse_err("gameplay w up\n# comment\ngameplay w down"),
+            (3, "w is already bound in gameplay on line 1".to_string())
+        );
+        assert_eq!(
+            parse_err("gameplay alt+enter fullscreen\ngameplay alt+enter start").1,
+            "alt+enter is already bound in gameplay on line 1"
+        );
+
+        // The same input with other modifiers or in another context is not a duplicate
+        let map =
+            ActionMap::parse("gameplay enter start\ngameplay alt+enter fullscreen\ndebug enter up");
+        assert!(map.is_ok());
+    }
+
+    #[test]
+    fn context_priority() {
+        let mut map =
+            ActionMap::parse("gameplay 1 up\ndebug 1 loop_slot 1\neditor 1 down").unwrap();
+
+        // Debug hides gameplay, and editor hides both once turned on
+        assert_eq!(char_action(&map, '1'), Some(Action::LoopSlot(1)));
+        map.toggle(Context::Editor);
+        assert_eq!(char_action(&map, '1'), Some(Action::Button(Button::Down)));
+
+        map.toggle(Context::Editor);
+        map.toggle(Context::Debug);
+        assert!(!map.is_active(Context::Debug));
+        assert_eq!(char_action(&map, '1'), Some(Action::Button(Button::Up)));
+
+        // Buttons are released regardless of the active contexts
+        let buttons = map.buttons(Input::Key(Key::Char('1'))).collect::<Vec<_>>();
+        assert_eq!(buttons, [Button::Up, Button::Down]);
+    }
+
+    #[test]
+    fn modifiers() {
+        let map = ActionMap::parse("gameplay enter start\ngameplay alt+enter fullscreen").unwrap();
+        let alt = Modifiers {
+            alt: true,
+            ..Modifiers::default()
+        };
+
+        assert_eq!(
+            map.action(Input::Key(Key::Enter), Modifiers::default()),
+            Some(Action::Button(Button::Start))
+        );
+        assert_eq!(
+            map.action(Input::Key(Key::Enter), alt),
+            Some(Action::Fullscreen)
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Minimal BMP writer for dumping framebuffers to disk
+//!
+//! Frames are written as 32 bit bottom-up bitmaps with a `BITMAPV4HEADER` so they can be
+//! read back with [`game_state::BitmapAsset::from_data`].
+
+use std::io::Write;
+use std::path::Path;
+
+/// Size of the `BITMAPFILEHEADER`
+const FILE_HEADER_SIZE: u32 = 14;
+
+/// Size of the `BITMAPV4HEADER`
+const INFO_HEADER_SIZE: u32 = 108;
+
+/// `BI_BITFIELDS` compression, the color channels are described by the masks
+const BI_BITFIELDS: u32 = 3;
+
+/// `LCS_sRGB` color space
+const LCS_SRGB: u32 = 0x7352_4742;
+
+/// Encode the `0xAARRGGBB` `pixels` of a `width` x `height` framebuffer as a BMP
+///
+/// The alpha channel of the framebuffer is not meaningful to the X server, so every
+/// pixel is written as fully opaque.
+///
+/// # Panics
+///
+/// * `pixels` is not `width * height` in length
+pub fn encode(width: u32, height: u32, pixels: &[u32]) -> Vec<u8> {
+    assert_eq!(
+        pixels.len(),
+        width as usize * height as usize,
+        "Framebuffer size mismatch"
+    );
+
+    let data_offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+    let image_size = width * height * 4;
+
+    let mut res = Vec::with_capacity((data_offset + image_size) as usize);
+
+    // BITMAPFILEHEADER
+    res.extend(b"BM");
+    res.extend((data_offset + image_size).to_le_bytes());
+    res.extend(0_u32.to_le_bytes());
+    res.extend(data_offset.to_le_bytes());
+
+    // BITMAPV4HEADER
+    res.extend(INFO_HEADER_SIZE.to_le_bytes());
+    res.extend(width.to_le_bytes());
+    res.extend(height.to_le_bytes());
+    res.extend(1_u16.to_le_bytes());
+    res.extend(32_u16.to_le_bytes());
+    res.extend(BI_BITFIELDS.to_le_bytes());
+    res.extend(image_size.to_le_bytes());
+    res.extend(2835_u32.to_le_bytes());
+    res.extend(2835_u32.to_le_bytes());
+    res.extend(0_u32.to_le_bytes());
+    res.extend(0_u32.to_le_bytes());
+
+    // Red, green, blue, and alpha masks
+    res.extend(0x00ff_0000_u32.to_le_bytes());
+    res.extend(0x0000_ff00_u32.to_le_bytes());
+    res.extend(0x0000_00ff_u32.to_le_bytes());
+    res.extend(0xff00_0000_u32.to_le_bytes());
+
+    // Color space, unused endpoints, and unused gamma
+    res.extend(LCS_SRGB.to_le_bytes());
+    res.extend([0_u8; 36 + 12]);
+
+    assert_eq!(res.len(), data_offset as usize);
+
+    // BMP pixels are stored from the bottom row to the top row
+    for row in pixels.chunks_exact(width as usize).rev() {
+        for pixel in row {
+            res.extend((pixel | 0xff00_0000).to_le_bytes());
+        }
+    }
+
+    res
+}
+
+/// Write the `0xAARRGGBB` `pixels` of a `width` x `height` framebuffer to `path`
+pub fn write(path: &Path, width: u32, height: u32, pixels: &[u32]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(&encode(width, height, pixels))
+}
@@ -3,9 +3,12 @@
 use std::ffi::{CStr, CString};
 use std::marker::PhantomData;
 use std::os::raw::{c_char, c_void};
-use std::time::SystemTime;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use game_state::{Game, State};
+use game_state::{Game, GameAbi, SoundBuffer, State};
+
+use crate::watch::LibraryWatcher;
 
 #[link(name = "dl")]
 extern "C" {
@@ -18,8 +21,57 @@ extern "C" {
 /// Lazy funcdtion call binding
 pub const RTLD_LAZY: u32 = 1;
 
-/// The library game logic to query for hot reload
-pub const LIBGAME: &'static str = "./target/release/libgame.so";
+/// The default library game logic to query for hot reload
+pub const LIBGAME: &str = "./target/release/libgame.so";
+
+/// Errors that can occur while loading the game library
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to copy the game library to its temporary location
+    Copy(PathBuf, std::io::Error),
+
+    /// `dlopen` failed for the game library
+    Open(CString),
+
+    /// A required export was not found in the game library
+    MissingSymbol(&'static str, CString),
+
+    /// The game library was built against a different interface than the platform
+    IncompatibleAbi {
+        /// The field of the [`GameAbi`] that differs
+        field: &'static str,
+
+        /// The value exported by the game library
+        library: u64,
+
+        /// The value expected by the platform
+        platform: u64,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Copy(path, err) => write!(f, "failed to copy {}: {err}", path.display()),
+            Error::Open(err) => write!(f, "dlopen failed: {}", err.to_string_lossy()),
+            Error::MissingSymbol(name, err) => {
+                write!(f, "missing export {name}: {}", err.to_string_lossy())
+            }
+            Error::IncompatibleAbi {
+                field,
+                library,
+                platform,
+            } => write!(
+                f,
+                "incompatible game library: {field} is {library:#x}, the platform expects \
+                 {platform:#x}"
+            ),
+        }
+    }
+}
+
+/// Custom [`Result`] type for loading the game library
+pub type Result<T> = std::result::Result<T, Error>;
 
 /// Handle to an opened shared library
 #[repr(transparent)]
@@ -32,32 +84,38 @@ pub struct GameFuncs {
     /// enable `Drop`
     pub handle: Handle,
 
-    /// Dummy test function
-    pub game_update_and_render: Symbol<extern "C" fn(&Game, &mut State)>,
+    /// Update and render a frame. Takes `&mut Game` since the game reports errors back
+    /// through `Game.error`.
+    pub game_update_and_render: Symbol<extern "C" fn(&mut Game, &mut State)>,
+
+    /// Fill a buffer with the next sound samples. Errors are reported through
+    /// `Game.error`.
+    pub game_get_sound_samples: Symbol<extern "C" fn(&mut Game, &mut State, &mut SoundBuffer)>,
 
-    /// The creation time of the currently loaded library used to check if we should
-    /// reload
-    pub created_time: SystemTime,
+    /// Number of game libraries loaded before this one, distinguishing each load
+    pub generation: usize,
 }
 
 impl GameFuncs {
-    /// Drop the old game library and reload the new one
-    pub fn reload(self) -> Self {
-        // If the library hasn't been updated, no need to reload it
-        if !self.is_library_updated() {
+    /// Swap in the game library once `watcher` sees it rebuilt, if it is compatible with
+    /// the platform. Otherwise, keep running the current library and print why the
+    /// update was refused.
+    pub fn reload(self, watcher: &mut LibraryWatcher) -> Self {
+        // If the library hasn't been rebuilt, no need to reload it
+        if !watcher.poll() {
             return self;
         }
 
-        // Drop the old library handle
-        drop(self);
-
-        // Reload the new game handle
-        get_game_funcs()
-    }
-
-    /// Returns true if the library has been modified
-    pub fn is_library_updated(&self) -> bool {
-        get_library_creation_time() != self.created_time
+        // Load the new library alongside the current one so the current one keeps
+        // running if the new one is refused
+        match load_game_funcs(watcher.path()) {
+            // Dropping the old library handle on return
+            Ok(game_funcs) => game_funcs,
+            Err(err) => {
+                println!("Hot reload: {err}, keeping the current game library");
+                self
+            }
+        }
     }
 }
 
@@ -90,7 +148,10 @@ impl<T> std::ops::Deref for Symbol<T> {
 }
 
 /// Get the requested [`Symbol`] by export name using the given library handle
-pub fn get_symbol<T>(library: Handle, symbol_name: &str) -> Result<Symbol<T>, CString> {
+pub fn get_symbol<T>(
+    library: Handle,
+    symbol_name: &str,
+) -> std::result::Result<Symbol<T>, CString> {
     // Get the `game_update_and_render` func from the game library
     unsafe {
         let symbol_name = CString::new(symbol_name)
@@ -111,49 +172,85 @@ pub fn get_symbol<T>(library: Handle, symbol_name: &str) -> Result<Symbol<T>, CS
     }
 }
 
-/// Location of the copied game logic library used to enable hot reload
-const TMP_FILE: &str = "/tmp/.libgame.so";
-
-fn get_library_creation_time() -> SystemTime {
-    for _ in 0..10 {
-        let Ok(metadata) = std::fs::metadata(LIBGAME) else {
-            continue;
-        };
-
-        let Ok(created) = metadata.created() else {
-            continue;
-        };
-
-        return created;
-    }
-
-    panic!("Failed to get library creation time");
+/// Prefix of the copied game logic library used to enable hot reload. Each load uses a
+/// new copy since `dlopen` returns the already loaded library for a path still in use,
+/// and each process uses its own copies so running instances don't collide.
+const TMP_FILE_PREFIX: &str = "/tmp/.libgame";
+
+/// Number of times the game library has been copied, used to name each copy
+static LOAD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Load and return the function pointers from the game code at `path`
+///
+/// # Panics
+///
+/// * The game library can't be loaded or is incompatible with the platform
+pub fn get_game_funcs(path: &Path) -> GameFuncs {
+    load_game_funcs(path).unwrap_or_else(|err| panic!("Failed to load {}: {err}", path.display()))
 }
 
-/// Load and return the function pointers from the game code
-pub fn get_game_funcs() -> GameFuncs {
-    // Copy the current game library into a temp file for hot reload. Ignore the failure
-    // copy case and pick up the game logic on the next frame
-    let _discard = std::fs::copy(LIBGAME, TMP_FILE);
-
-    let created_time = get_library_creation_time();
+/// Load the game code at `path`, checking that it was built against the same interface
+/// as the platform before returning its function pointers
+pub fn load_game_funcs(path: &Path) -> Result<GameFuncs> {
+    // Copy the current game library into a new temp file for hot reload
+    let generation = LOAD_COUNT.fetch_add(1, Ordering::SeqCst);
+    let tmp_file = format!("{TMP_FILE_PREFIX}.{}.{generation}.so", std::process::id());
+    std::fs::copy(path, &tmp_file).map_err(|err| Error::Copy(path.to_path_buf(), err))?;
 
     // Get the temporary library file
-    let library = CString::new(TMP_FILE).expect("CString failed for tmp library");
+    let library = CString::new(tmp_file.as_str()).expect("CString failed for tmp library");
 
-    unsafe {
-        // Open the  current game dynamic library
-        let handle = dlopen(library.as_ptr(), RTLD_LAZY);
-        assert!(handle.0 != 0, "libgame.so not found");
+    // Open the current game dynamic library
+    let handle = unsafe { dlopen(library.as_ptr(), RTLD_LAZY) };
 
-        // Get the `game_update_and_render` export
-        let game_update_and_render = get_symbol(handle, "game_update_and_render").unwrap();
+    // The library stays mapped after the copy is removed
+    let _discard = std::fs::remove_file(&tmp_file);
 
-        // Return the exported game functions
-        GameFuncs {
-            handle,
-            game_update_and_render,
-            created_time,
+    if handle.0 == 0 {
+        return Err(Error::Open(unsafe { CStr::from_ptr(dlerror()).into() }));
+    }
+
+    // Close the library if it is refused
+    let close = |err| {
+        unsafe {
+            dlclose(handle);
         }
+
+        Err(err)
+    };
+
+    // Check the library was built against the same interface as the platform
+    let abi = match get_symbol::<*const GameAbi>(handle, "GAME_ABI") {
+        // SAFETY: `GAME_ABI` is exported as a `GameAbi` static
+        Ok(abi) => unsafe { **abi },
+        Err(err) => return close(Error::MissingSymbol("GAME_ABI", err)),
+    };
+
+    if let Some((field, library, platform)) = abi.mismatch(&GameAbi::CURRENT) {
+        return close(Error::IncompatibleAbi {
+            field,
+            library,
+            platform,
+        });
     }
+
+    // Get the `game_update_and_render` export
+    let game_update_and_render = match get_symbol(handle, "game_update_and_render") {
+        Ok(symbol) => symbol,
+        Err(err) => return close(Error::MissingSymbol("game_update_and_render", err)),
+    };
+
+    // Get the `game_get_sound_samples` export
+    let game_get_sound_samples = match get_symbol(handle, "game_get_sound_samples") {
+        Ok(symbol) => symbol,
+        Err(err) => return close(Error::MissingSymbol("game_get_sound_samples", err)),
+    };
+
+    // Return the exported game functions
+    Ok(GameFuncs {
+        handle,
+        game_update_and_render,
+        game_get_sound_samples,
+        generation,
+    })
 }
@@ -0,0 +1,798 @@
+//! Gamepad input read from Linux evdev devices
+//!
+//! Devices under `/dev/input` that report gamepad buttons and a left stick are used as
+//! the controllers after the keyboard, in the order they are found, and are searched for
+//! again every few seconds. A recording of a device written by `evemu-record` can be
+//! replayed in place of the device:
+//!
+//! ```text
+//! N: Microsoft X-Box 360 pad
+//! A: 00 -32768 32767 16 128 0
+//! A: 01 -32768 32767 16 128 0
+//! E: 0.000000 0003 0000 24000
+//! E: 0.000000 0000 0000 0000
+//! E: 0.500000 0001 0137 1
+//! ```
+//!
+//! `A:` lines give the range of an axis as its code, minimum, maximum, fuzz, flat, and
+//! resolution. `E:` lines are an event as its time in seconds, type, code, and value.
+//! Every other line is ignored.
+
+use std::fs::File;
+use std::io::Read;
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use game_state::{Button, Controller, KEYBOARD_CONTROLLER, MAX_CONTROLLERS};
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+/// Directory searched for gamepads
+const INPUT_DIR: &str = "/dev/input";
+
+/// Time between searches for a gamepad while none is connected
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Smallest deadzone of the stick as a fraction of its range. Devices often report a
+/// `flat` range far smaller than the drift of a worn stick.
+const DEADZONE: f32 = 0.24;
+
+/// Size (in bytes) of a `struct input_event` on 64-bit Linux: a 16 byte `timeval`, the
+/// type and code as `u16`, and the value as `i32`
+const EVENT_SIZE: usize = 24;
+
+/// `O_NONBLOCK` from `fcntl.h`
+const O_NONBLOCK: i32 = 0o4000;
+
+// Event types from `linux/input-event-codes.h`
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+
+// Axes from `linux/input-event-codes.h`
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+// Buttons from `linux/input-event-codes.h`
+const BTN_GAMEPAD: u16 = 0x130;
+const BTN_TL: u16 = 0x136;
+const BTN_TR: u16 = 0x137;
+const BTN_START: u16 = 0x13b;
+const BTN_DPAD_UP: u16 = 0x220;
+const BTN_DPAD_DOWN: u16 = 0x221;
+const BTN_DPAD_LEFT: u16 = 0x222;
+const BTN_DPAD_RIGHT: u16 = 0x223;
+
+/// Highest button code, sizing the button capability bits
+const KEY_MAX: usize = 0x2ff;
+
+/// Build an evdev `ioctl` request that reads `len` bytes for command `nr`
+const fn evdev_read(nr: u8, len: usize) -> c_ulong {
+    (2 << 30) | ((len as c_ulong) << 16) | ((b'E' as c_ulong) << 8) | nr as c_ulong
+}
+
+/// Errors that can occur while opening a gamepad
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read the recorded events
+    ReadRecording(PathBuf, std::io::Error),
+
+    /// A line of the recorded events failed to parse
+    InvalidRecording {
+        /// Line number (starting at 1) of the bad line
+        line: usize,
+
+        /// Why the line is invalid
+        reason: String,
+    },
+
+    /// More recordings were given than there are gamepad controllers
+    TooManyRecordings(usize),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ReadRecording(path, err) => {
+                write!(f, "failed to read recording {}: {err}", path.display())
+            }
+            Error::InvalidRecording { line, reason } => {
+                write!(f, "recording line {line}: {reason}")
+            }
+            Error::TooManyRecordings(count) => write!(
+                f,
+                "{count} recordings given, only {} gamepads are supported",
+                MAX_CONTROLLERS - 1
+            ),
+        }
+    }
+}
+
+/// Custom [`Result`] type for the gamepad
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A single evdev event
+#[derive(Debug, Copy, Clone)]
+struct Event {
+    /// Type of the event, such as `EV_KEY`
+    kind: u16,
+
+    /// Button or axis that changed
+    code: u16,
+
+    /// New value of the button or axis
+    value: i32,
+}
+
+/// Range of an analog axis, from `struct input_absinfo`
+#[derive(Debug, Copy, Clone)]
+struct Axis {
+    /// Smallest reported value
+    minimum: i32,
+
+    /// Largest reported value
+    maximum: i32,
+
+    /// Values this close to the center are reported as centered by the device
+    flat: i32,
+}
+
+impl Default for Axis {
+    fn default() -> Self {
+        Self {
+            minimum: i32::from(i16::MIN),
+            maximum: i32::from(i16::MAX),
+            flat: 0,
+        }
+    }
+}
+
+impl Axis {
+    /// Get the position of `value` from -1.0 to 1.0, with values inside the deadzone
+    /// centered and the rest of the range scaled to start at the edge of the deadzone
+    #[allow(clippy::cast_possible_truncation)]
+    fn normalize(&self, value: i32) -> f32 {
+        let center = (f64::from(self.minimum) + f64::from(self.maximum)) / 2.;
+        let half_range = ((f64::from(self.maximum) - f64::from(self.minimum)) / 2.).max(1.);
+
+        let position = ((f64::from(value) - center) / half_range).clamp(-1., 1.) as f32;
+        let deadzone = (f64::from(self.flat) / half_range).max(f64::from(DEADZONE)) as f32;
+
+        if position.abs() <= deadzone {
+            return 0.;
+        }
+
+        (position - deadzone.copysign(position)) / (1. - deadzone)
+    }
+}
+
+/// Where the events of a [`Gamepad`] come from
+enum Source {
+    /// An evdev device opened without blocking
+    Device(File),
+
+    /// Recorded events replayed at the time they were recorded
+    Recording {
+        /// The (time since the first event, event) pairs of the recording
+        events: Vec<(Duration, Event)>,
+
+        /// Index of the next event to replay
+        next: usize,
+    },
+}
+
+/// A gamepad read from evdev or a recording
+pub struct Gamepad {
+    /// Where the events come from
+    source: Source,
+
+    /// Path of the device or recording
+    path: PathBuf,
+
+    /// Name of the device
+    name: String,
+
+    /// Range of the horizontal and vertical axes of the left stick
+    stick: [Axis; 2],
+
+    /// Raw horizontal and vertical position of the left stick
+    raw_stick: [i32; 2],
+}
+
+impl Gamepad {
+    /// Open the evdev device at `path`, returning `None` if it can't be read or isn't a
+    /// gamepad
+    pub fn open(path: &Path) -> Option<Self> {
+        let file = File::options()
+            .read(true)
+            .custom_flags(O_NONBLOCK)
+            .open(path)
+            .ok()?;
+
+        let name = Self::gamepad_name(&file)?;
+
+        // SAFETY: `EVIOCGABS` fills a `struct input_absinfo` of six `i32`s
+        let axis = |code: u16| unsafe {
+            let mut info = [0_i32; 6];
+            let request = evdev_read(0x40 + code as u8, std::mem::size_of_val(&info));
+            let res = ioctl(file.as_raw_fd(), request, info.as_mut_ptr());
+            (res >= 0).then_some((
+                info[0],
+                Axis {
+                    minimum: info[1],
+                    maximum: info[2],
+                    flat: info[4],
+                },
+            ))
+        };
+
+        let (x, y) = (axis(ABS_X), axis(ABS_Y));
+
+        Some(Self {
+            stick: [
+                x.map_or_else(Axis::default, |(_, axis)| axis),
+                y.map_or_else(Axis::default, |(_, axis)| axis),
+            ],
+            raw_stick: [
+                x.map_or(0, |(value, _)| value),
+                y.map_or(0, |(value, _)| value),
+            ],
+            source: Source::Device(file),
+            path: path.to_path_buf(),
+            name,
+        })
+    }
+
+    /// Create a [`Gamepad`] that replays the events recorded at `path`
+    pub fn replay(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Error::ReadRecording(path.to_path_buf(), err))?;
+
+        let mut name = format!("replay of {}", path.display());
+        let mut stick = [Axis::default(); 2];
+        let mut events = Vec::new();
+        let mut start = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: String| Error::InvalidRecording {
+                line: index + 1,
+                reason,
+            };
+
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((kind, fields)) = line.split_once(':') else {
+                continue;
+            };
+
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            let hex = |field: &str| {
+                u16::from_str_radix(field, 16)
+                    .map_err(|_| invalid(format!("invalid code: {field}")))
+            };
+            let int = |field: &str| {
+                field
+                    .parse::<i32>()
+                    .map_err(|_| invalid(format!("invalid value: {field}")))
+            };
+
+            match (kind, fields.as_slice()) {
+                ("N", _) => name = fields.join(" "),
+                ("A", [code, minimum, maximum, _fuzz, flat, ..]) => {
+                    let axis = Axis {
+                        minimum: int(minimum)?,
+                        maximum: int(maximum)?,
+                        flat: int(flat)?,
+                    };
+
+                    match hex(code)? {
+                        ABS_X => stick[0] = axis,
+                        ABS_Y => stick[1] = axis,
+                        _ => {}
+                    }
+                }
+                ("E", [time, kind, code, value]) => {
+                    let time = time
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|time| time.is_finite() && *time >= 0.)
+                        .ok_or_else(|| invalid(format!("invalid time: {time}")))?;
+
+                    let start = *start.get_or_insert(time);
+                    let time = Duration::from_secs_f64((time - start).max(0.));
+
+                    let event = Event {
+                        kind: hex(kind)?,
+                        code: hex(code)?,
+                        value: int(value)?,
+                    };
+
+                    events.push((time, event));
+                }
+                ("A" | "E", _) => return Err(invalid(format!("malformed {kind} line"))),
+                _ => {}
+            }
+        }
+
+        // Replay events in the order they happened
+        events.sort_by_key(|(time, _)| *time);
+
+        Ok(Self {
+            source: Source::Recording { events, next: 0 },
+            path: path.to_path_buf(),
+            name,
+            stick,
+            raw_stick: [0; 2],
+        })
+    }
+
+    /// Apply the events that happened up to `now` to the `controller`. `now` is the time
+    /// since the game started, which paces the replay of a recording.
+    ///
+    /// Returns false if the device was unplugged, leaving the `controller` disconnected.
+    pub fn poll(&mut self, now: Duration, controller: &mut Controller) -> bool {
+        let mut events = Vec::new();
+
+        match &mut self.source {
+            Source::Device(file) => {
+                let mut buffer = [0_u8; EVENT_SIZE * 64];
+
+                loop {
+                    match file.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(len) => {
+                            events.extend(buffer[..len].chunks_exact(EVENT_SIZE).map(|raw| {
+                                Event {
+                                    kind: u16::from_ne_bytes([raw[16], raw[17]]),
+                                    code: u16::from_ne_bytes([raw[18], raw[19]]),
+                                    value: i32::from_ne_bytes([raw[20], raw[21], raw[22], raw[23]]),
+                                }
+                            }));
+                        }
+                        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                        Err(_) => {
+                            *controller = Controller::default();
+                            return false;
+                        }
+                    }
+                }
+            }
+            Source::Recording {
+                events: recorded,
+                next,
+            } => {
+                while let Some((_, event)) = recorded.get(*next).filter(|(time, _)| *time <= now) {
+                    events.push(*event);
+                    *next += 1;
+                }
+            }
+        }
+
+        controller.is_connected = true;
+
+        for event in events {
+            self.apply(event, controller);
+        }
+
+        true
+    }
+
+    /// Update the `controller` with a single `event`
+    fn apply(&mut self, event: Event, controller: &mut Controller) {
+        let buttons = &mut controller.buttons;
+        let mut set = |button: Button, is_down: bool| buttons[button as usize].process(is_down);
+
+        match (event.kind, event.code) {
+            (EV_KEY, code) => {
+                let is_down = event.value != 0;
+                match code {
+                    BTN_DPAD_UP => set(Button::Up, is_down),
+                    BTN_DPAD_DOWN => set(Button::Down, is_down),
+                    BTN_DPAD_LEFT => set(Button::Left, is_down),
+                    BTN_DPAD_RIGHT => set(Button::Right, is_down),
+                    BTN_TL => set(Button::DecreaseSpeed, is_down),
+                    BTN_TR => set(Button::IncreaseSpeed, is_down),
+                    BTN_START => set(Button::Start, is_down),
+                    _ => {}
+                }
+            }
+            (EV_ABS, ABS_HAT0X) => {
+                set(Button::Left, event.value < 0);
+                set(Button::Right, event.value > 0);
+            }
+            (EV_ABS, ABS_HAT0Y) => {
+                set(Button::Up, event.value < 0);
+                set(Button::Down, event.value > 0);
+            }
+            (EV_ABS, ABS_X) => self.raw_stick[0] = event.value,
+            (EV_ABS, ABS_Y) => self.raw_stick[1] = event.value,
+            _ => {}
+        }
+
+        // evdev reports down as positive, the game uses up as positive
+        controller.stick.x = self.stick[0].normalize(self.raw_stick[0]);
+        controller.stick.y = -self.stick[1].normalize(self.raw_stick[1]);
+    }
+
+    /// Get the name of the device opened as `file` if it is a gamepad
+    fn gamepad_name(file: &File) -> Option<String> {
+        let fd = file.as_raw_fd();
+
+        // SAFETY: Each request writes at most the length of its buffer
+        unsafe {
+            let mut keys = [0_u8; KEY_MAX / 8 + 1];
+            let request = evdev_read(0x20 + EV_KEY as u8, keys.len());
+            if ioctl(fd, request, keys.as_mut_ptr()) < 0 {
+                return None;
+            }
+
+            let mut axes = [0_u8; 8];
+            let request = evdev_read(0x20 + EV_ABS as u8, axes.len());
+            if ioctl(fd, request, axes.as_mut_ptr()) < 0 {
+                return None;
+            }
+
+            let has_key = |code: u16| keys[usize::from(code / 8)] & (1 << (code % 8)) != 0;
+            let has_axis = |code: u16| axes[usize::from(code / 8)] & (1 << (code % 8)) != 0;
+
+            if !has_key(BTN_GAMEPAD) || !has_axis(ABS_X) || !has_axis(ABS_Y) {
+                return None;
+            }
+
+            let mut name = [0_u8; 256];
+            let request = evdev_read(0x06, name.len() - 1);
+            let len = ioctl(fd, request, name.as_mut_ptr());
+            let name = &name[..usize::try_from(len).unwrap_or(0)];
+            let name = String::from_utf8_lossy(name);
+
+            Some(name.trim_end_matches('\0').to_string())
+        }
+    }
+}
+
+/// The gamepads driving every controller after the keyboard
+pub struct Gamepads {
+    /// The gamepad of each controller after [`KEYBOARD_CONTROLLER`], so the first pad is
+    /// controller `KEYBOARD_CONTROLLER + 1`
+    pads: [Option<Gamepad>; MAX_CONTROLLERS - 1],
+
+    /// Search `/dev/input` for newly connected gamepads
+    detect: bool,
+
+    /// When to next search for gamepads
+    next_scan: Duration,
+}
+
+impl Gamepads {
+    /// Create [`Gamepads`] that use the gamepads under `/dev/input` as they are plugged in
+    pub fn detect() -> Self {
+        Self {
+            pads: Default::default(),
+            detect: true,
+            next_scan: Duration::ZERO,
+        }
+    }
+
+    /// Create [`Gamepads`] that replay the recordings at `paths` as the controllers
+    /// after the keyboard, in order
+    pub fn replay(paths: &[PathBuf]) -> Result<Self> {
+        let mut gamepads = Self::detect();
+        gamepads.detect = false;
+
+        if paths.len() > gamepads.pads.len() {
+            return Err(Error::TooManyRecordings(paths.len()));
+        }
+
+        for (pad, path) in gamepads.pads.iter_mut().zip(paths) {
+            *pad = Some(Gamepad::replay(path)?);
+        }
+
+        Ok(gamepads)
+    }
+
+    /// Apply the events that happened up to `now` to the gamepad `controllers`. The
+    /// keyboard controller is left untouched.
+    pub fn poll(&mut self, now: Duration, controllers: &mut [Controller; MAX_CONTROLLERS]) {
+        if self.detect && now >= self.next_scan {
+            self.next_scan = now + SCAN_INTERVAL;
+            self.scan();
+        }
+
+        let gamepad_controllers = &mut controllers[KEYBOARD_CONTROLLER + 1..];
+
+        for (index, (slot, controller)) in self.pads.iter_mut().zip(gamepad_controllers).enumerate()
+        {
+            let Some(pad) = slot else {
+                continue;
+            };
+
+            if !pad.poll(now, controller) {
+                println!(
+                    "Gamepad: {} disconnected from controller {}",
+                    pad.name,
+                    KEYBOARD_CONTROLLER + 1 + index
+                );
+                *slot = None;
+            }
+        }
+    }
+
+    /// Open the gamepads under `/dev/input` that aren't already open into the free
+    /// controllers
+    fn scan(&mut self) {
+        if self.pads.iter().all(Option::is_some) {
+            return;
+        }
+
+        let Ok(entries) = std::fs::read_dir(INPUT_DIR) else {
+            return;
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("event"))
+            })
+            .filter(|path| !self.pads.iter().flatten().any(|pad| pad.path == *path))
+            .collect();
+
+        // Assign the earliest connected devices first, such as event2 before event10
+        paths.sort_by_key(|path| {
+            let name = path.file_name().and_then(|name| name.to_str());
+            name.and_then(|name| name["event".len()..].parse::<u32>().ok())
+        });
+
+        let mut gamepads = paths.iter().filter_map(|path| Gamepad::open(path));
+
+        for (index, slot) in self.pads.iter_mut().enumerate() {
+            if slot.is_some() {
+                continue;
+            }
+
+            let Some(pad) = gamepads.next() else {
+                break;
+            };
+
+            println!(
+                "Gamepad: {} ({}) connected as controller {}",
+                pad.name,
+                pad.path.display(),
+                KEYBOARD_CONTROLLER + 1 + index
+            );
+            *slot = Some(pad);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `recording` into a file for the test called `name`, returning its path
+    fn write_recording(name: &str, recording: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("evemu_{name}_{}.evemu", std::process::id()));
+        std::fs::write(&path, recording).unwrap();
+        path
+    }
+
+    /// Replay `recording` as the first gamepad, returning the error it fails with
+    fn replay_err(name: &str, recording: &str) -> Error {
+        let path = write_recording(name, recording);
+        let res = Gamepads::replay(std::slice::from_ref(&path));
+        std::fs::remove_file(path).unwrap();
+
+        res.err().expect("replayed an invalid recording")
+    }
+
+    /// Start a new frame for the `controllers` and poll `gamepads` at `seconds`
+    fn poll_at(
+        gamepads: &mut Gamepads,
+        controllers: &mut [Controller; MAX_CONTROLLERS],
+        seconds: f64,
+    ) {
+        controllers
+            .iter_mut()
+            .flat_map(|controller| controller.buttons.iter_mut())
+            .for_each(|button| button.begin_frame());
+
+        gamepads.poll(Duration::from_secs_f64(seconds), controllers);
+    }
+
+    #[test]
+    fn normalize_full_range() {
+        let axis = Axis::default();
+
+        assert_eq!(axis.normalize(i32::from(i16::MIN)), -1.);
+        assert_eq!(axis.normalize(i32::from(i16::MAX)), 1.);
+        assert_eq!(axis.normalize(0), 0.);
+
+        // Values past the reported range are clamped
+        assert_eq!(axis.normalize(100_000), 1.);
+    }
+
+    #[test]
+    fn normalize_deadzone() {
+        let axis = Axis {
+            minimum: -1000,
+            maximum: 1000,
+            flat: 0,
+        };
+
+        // Inside the deadzone is centered, and just outside it starts from zero
+        assert_eq!(axis.normalize(240), 0.);
+        assert_eq!(axis.normalize(-240), 0.);
+        assert!(axis.normalize(250) > 0. && axis.normalize(250) < 0.02);
+        assert!(axis.normalize(-250) < 0. && axis.normalize(-250) > -0.02);
+
+        // A flat range wider than the deadzone is used instead
+        let axis = Axis {
+            minimum: -1000,
+            maximum: 1000,
+            flat: 500,
+        };
+        assert_eq!(axis.normalize(490), 0.);
+        assert!((axis.normalize(750) - 0.5).abs() < 1e-6);
+        assert_eq!(axis.normalize(1000), 1.);
+    }
+
+    #[test]
+    fn normalize_unsigned_range() {
+        let axis = Axis {
+            minimum: 0,
+            maximum: 255,
+            flat: 15,
+        };
+
+        assert_eq!(axis.normalize(0), -1.);
+        assert_eq!(axis.normalize(255), 1.);
+        assert_eq!(axis.normalize(128), 0.);
+    }
+
+    #[test]
+    fn replay_recording() {
+        let path = write_recording(
+            "replay",
+            "\
+# EVEMU 1.3
+N: Test Pad
+I: 0003 045e 028e 0114
+A: 00 0 255 0 15 0
+A: 01 0 255 0 15 0
+A: 10 -1 1 0 0 0
+
+E: 1.000000 0003 0000 255     # Stick right
+E: 1.000000 0003 0001 0       # Stick up
+E: 1.000000 0000 0000 0000    # SYN_REPORT
+E: 1.500000 0001 013b 1       # Start tapped
+E: 1.600000 0001 013b 0
+E: 2.000000 0003 0010 -1      # Hat left
+E: 2.000000 0003 0011 1       # Hat down
+E: 2.500000 0003 0011 0       # Hat released
+E: 2.500000 0001 0220 1       # D-pad up
+E: 2.500000 0001 0136 1       # Left shoulder
+E: 2.500000 0001 02ff 1       # Unknown button
+E: 2.500000 0003 0028 5       # Unknown axis
+",
+        );
+
+        let mut gamepads = Gamepads::replay(std::slice::from_ref(&path)).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let mut controllers = [Controller::default(); MAX_CONTROLLERS];
+        let pad = KEYBOARD_CONTROLLER + 1;
+
+        // Times are from the first event, and the stick is flipped to have up positive
+        poll_at(&mut gamepads, &mut controllers, 0.);
+        assert!(controllers[pad].is_connected);
+        assert!(!controllers[KEYBOARD_CONTROLLER].is_connected);
+        assert_eq!(
+            (controllers[pad].stick.x, controllers[pad].stick.y),
+            (1., 1.)
+        );
+        assert_eq!(gamepads.pads[0].as_ref().unwrap().name, "Test Pad");
+
+        // Events are only replayed once their time is reached
+        poll_at(&mut gamepads, &mut controllers, 0.4);
+        assert_eq!(
+            controllers[pad].button(Button::Start).half_transition_count,
+            0
+        );
+
+        // A tap between two polls is still seen
+        poll_at(&mut gamepads, &mut controllers, 0.6);
+        let start = controllers[pad].button(Button::Start);
+        assert!(start.was_pressed() && start.was_released() && !start.is_down());
+
+        // The hat holds the d-pad buttons
+        poll_at(&mut gamepads, &mut controllers, 1.);
+        let buttons = |controllers: &[Controller; MAX_CONTROLLERS]| {
+            [
+                Button::Up,
+                Button::Down,
+                Button::Left,
+                Button::Right,
+                Button::DecreaseSpeed,
+            ]
+            .map(|button| controllers[pad].button(button).is_down())
+        };
+        assert_eq!(buttons(&controllers), [false, true, true, false, false]);
+        assert!(controllers[pad].button(Button::Left).was_pressed());
+
+        // Releasing the hat only releases its own axis, and unknown codes are ignored
+        poll_at(&mut gamepads, &mut controllers, 1.5);
+        assert_eq!(buttons(&controllers), [true, false, true, false, true]);
+        assert!(controllers[pad].button(Button::Down).was_released());
+        assert!(!controllers[pad].button(Button::Left).was_pressed());
+        assert_eq!(
+            (controllers[pad].stick.x, controllers[pad].stick.y),
+            (1., 1.)
+        );
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let recordings = [
+            (
+                "short_axis",
+                "N: Pad\nA: 00 -32768\n",
+                2,
+                "malformed A line",
+            ),
+            (
+                "long_event",
+                "E: 0.0 0003 0000 1 2\n",
+                1,
+                "malformed E line",
+            ),
+            (
+                "bad_time",
+                "E: 0.0 0003 0000 1\nE: -1.0 0003 0000 1\n",
+                2,
+                "invalid time: -1.0",
+            ),
+            ("bad_code", "E: 0.0 0003 zz 1\n", 1, "invalid code: zz"),
+            (
+                "bad_value",
+                "A: 00 -32768 32767 16 lots 0\n",
+                1,
+                "invalid value: lots",
+            ),
+        ];
+
+        for (name, recording, expected_line, expected_reason) in recordings {
+            match replay_err(name, recording) {
+                Error::InvalidRecording { line, reason } => {
+                    assert_eq!(
+                        (line, reason.as_str()),
+                        (expected_line, expected_reason),
+                        "{name}"
+                    );
+                }
+                err => panic!("{name}: {err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn replay_errors() {
+        let missing = std::env::temp_dir().join("evemu_missing.evemu");
+        assert!(matches!(
+            Gamepads::replay(&[missing]),
+            Err(Error::ReadRecording(..))
+        ));
+
+        let paths = vec![PathBuf::from("pad.evemu"); MAX_CONTROLLERS];
+        assert!(matches!(
+            Gamepads::replay(&paths),
+            Err(Error::TooManyRecordings(count)) if count == MAX_CONTROLLERS
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+//! Golden image regression tests for rendered frames
+//!
+//! Every `*.txt` file in the golden directory is a headless input script describing a
+//! named scenario. Each frame captured by a scenario is compared against the reference
+//! image of the same name in the golden directory. When a frame differs, the actual frame
+//! and a diff image highlighting the differing pixels are written to the output directory.
+//!
+//! Running with `--bless` rewrites the reference images from the current renderer.
+
+use std::path::{Path, PathBuf};
+
+use game_state::{BitmapAsset, Memory, PlayerBitmap, PlayerDirection, SoundAsset, SoundEffect};
+use game_state::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+
+use vector::Vector2;
+
+use crate::args::Options;
+use crate::headless::{Error, Result, Runner, Script};
+use crate::{bmp, dl};
+
+/// Seed used to generate the world for every scenario so the rendered rooms match
+pub const GOLDEN_SEED: u64 = 0x00c0_ffee;
+
+/// Color used in the diff image for pixels outside of the tolerance
+const DIFF_COLOR: u32 = 0x00ff_0000;
+
+/// The result of comparing a frame against its reference image
+#[derive(Debug, Default)]
+struct Comparison {
+    /// Number of pixels with a channel outside of the tolerance
+    mismatched: usize,
+
+    /// Largest difference seen in any color channel
+    max_delta: u8,
+
+    /// Image of the reference with the mismatched pixels highlighted
+    diff: Vec<u32>,
+}
+
+/// Get the largest difference between the red, green, and blue channels of two pixels
+fn channel_delta(left: u32, right: u32) -> u8 {
+    left.to_le_bytes()[..3]
+        .iter()
+        .zip(&right.to_le_bytes()[..3])
+        .map(|(left, right)| left.abs_diff(*right))
+        .max()
+        .unwrap_or_default()
+}
+
+/// Compare the `actual` framebuffer against the `reference` pixels
+fn compare(actual: &[u32], reference: &[u32], tolerance: u8) -> Comparison {
+    let mut res = Comparison::default();
+
+    for (actual, reference) in actual.iter().zip(reference) {
+        let delta = channel_delta(*actual, *reference);
+        res.max_delta = res.max_delta.max(delta);
+
+        if delta > tolerance {
+            res.mismatched += 1;
+            res.diff.push(DIFF_COLOR);
+        } else {
+            // Dim the matching pixels so the differences stand out
+            res.diff.push((reference >> 2) & 0x003f_3f3f);
+        }
+    }
+
+    res
+}
+
+/// Decode a reference image into `0x00RRGGBB` pixels in framebuffer order
+///
+/// Returns `None` if the reference is not the size of the framebuffer
+fn reference_pixels(data: &[u8]) -> Option<Vec<u32>> {
+    let asset = BitmapAsset::from_data(data);
+
+    if asset.width != u32::from(GAME_WINDOW_WIDTH) || asset.height != u32::from(GAME_WINDOW_HEIGHT)
+    {
+        return None;
+    }
+
+    let width = asset.width as usize;
+    let row_len = width * 4;
+    let red_index = usize::from(asset.red_index);
+    let green_index = usize::from(asset.green_index);
+    let blue_index = usize::from(asset.blue_index);
+
+    // BMP pixels are stored from the bottom row to the top row
+    let res = asset
+        .data
+        .get(..row_len * asset.height as usize)?
+        .chunks_exact(row_len)
+        .rev()
+        .flat_map(|row| row.chunks_exact(4))
+        .map(|pixel| {
+            u32::from(pixel[red_index]) << 16
+                | u32::from(pixel[green_index]) << 8
+                | u32::from(pixel[blue_index])
+        })
+        .collect();
+
+    Some(res)
+}
+
+/// Get the sorted scenario scripts in the golden directory
+fn scenarios(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|err| Error::ReadScript(dir.to_path_buf(), err))?;
+
+    let mut res = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|err| Error::ReadScript(dir.to_path_buf(), err))?
+            .path();
+
+        if path.extension().is_some_and(|ext| ext == "txt") {
+            res.push(path);
+        }
+    }
+
+    res.sort();
+    Ok(res)
+}
+
+/// Write a framebuffer sized image to `path`
+fn write_frame(path: &Path, pixels: &[u32]) -> Result<()> {
+    bmp::write(
+        path,
+        u32::from(GAME_WINDOW_WIDTH),
+        u32::from(GAME_WINDOW_HEIGHT),
+        pixels,
+    )
+    .map_err(|err| Error::WriteCapture(path.to_path_buf(), err))
+}
+
+/// Render every scenario in `golden_dir` and compare the captured frames against the
+/// reference images. Returns `true` if every frame matched.
+pub fn run(golden_dir: &Path, options: &Options) -> Result<bool> {
+    let scenarios = scenarios(golden_dir)?;
+
+    std::fs::create_dir_all(&options.output_dir)
+        .map_err(|err| Error::CreateOutput(options.output_dir.clone(), err))?;
+
+    load_game_assets!(player_assets, background, sound_assets);
+
+    let mut runner = Runner::new(
+        dl::get_game_funcs(&options.libgame),
+        Memory::new(),
+        player_assets,
+        &background,
+        sound_assets,
+    );
+
+    let mut failures = 0;
+    let mut frames_checked = 0;
+
+    for scenario in &scenarios {
+        let name = scenario.file_stem().unwrap_or_default().to_string_lossy();
+        let script = Script::from_file(scenario)?;
+
+        // Render the scenario, keeping every captured frame
+        let mut captures = Vec::new();
+        runner.run(
+            &script,
+            script.len(),
+            GOLDEN_SEED,
+            |_frame, path, framebuffer| {
+                captures.push((path.to_path_buf(), framebuffer.to_vec()));
+                Ok(())
+            },
+        )?;
+
+        for (path, actual) in captures {
+            let reference_path = golden_dir.join(&path);
+            frames_checked += 1;
+
+            if options.bless {
+                write_frame(&reference_path, &actual)?;
+                println!("{name}: blessed {}", reference_path.display());
+                continue;
+            }
+
+            let Some(reference) = std::fs::read(&reference_path)
+                .ok()
+                .and_then(|data| reference_pixels(&data))
+            else {
+                println!(
+                    "{name}: FAIL {} is missing or the wrong size (run with --bless)",
+                    reference_path.display()
+                );
+                failures += 1;
+                continue;
+            };
+
+            let comparison = compare(&actual, &reference, options.tolerance);
+            if comparison.mismatched == 0 {
+                println!("{name}: ok {}", path.display());
+                continue;
+            }
+
+            failures += 1;
+
+            // Write the actual frame and the diff image for inspection
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let actual_path = options.output_dir.join(format!("{stem}.actual.bmp"));
+            let diff_path = options.output_dir.join(format!("{stem}.diff.bmp"));
+            write_frame(&actual_path, &actual)?;
+            write_frame(&diff_path, &comparison.diff)?;
+
+            println!(
+                "{name}: FAIL {} has {} pixels differing by up to {} (tolerance {}), see {}",
+                path.display(),
+                comparison.mismatched,
+                comparison.max_delta,
+                options.tolerance,
+                diff_path.display()
+            );
+        }
+    }
+
+    println!(
+        "Golden: {} scenarios, {frames_checked} frames, {failures} failed",
+        scenarios.len()
+    );
+
+    Ok(failures == 0)
+}
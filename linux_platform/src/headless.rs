@@ -0,0 +1,445 @@
+//! Headless platform that drives the game logic without an X11 window
+//!
+//! The game is driven by an input script where each line is a frame number, a command,
+//! and the arguments for that command:
+//!
+//! ```text
+//! # frame  command  arguments
+//! 0        press    right
+//! 10       release  right
+//! 10       capture  facing_right.bmp
+//! ```
+//!
+//! `press` and `release` take a [`Button`] name and are applied before the frame is run.
+//! `capture` writes the framebuffer to the given file, relative to the output directory,
+//! after the frame has been rendered.
+//!
+//! The script drives the keyboard controller. Recorded gamepads replay alongside it, with
+//! each frame lasting `1 / TARGET_FRAMES_PER_SECOND` seconds of the recordings. The game
+//! is told each frame took exactly that long, however long it ran, so runs repeat. The
+//! game sound can be written into a WAV file, one frame of samples per frame.
+
+use core::mem::variant_count;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use game_state::profile::{self, Session, TimedBlock};
+use game_state::{timed_block, Profiler};
+use game_state::{
+    BitmapAsset, Button, ButtonState, Controller, Game, Memory, Mouse, PlayerBitmap,
+    PlayerDirection,
+};
+use game_state::{
+    SoundAsset, SoundBuffer, SoundEffect, SAMPLES_PER_SECOND, SOUND_CHANNELS,
+    TARGET_FRAMES_PER_SECOND,
+};
+use game_state::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH, KEYBOARD_CONTROLLER, MAX_CONTROLLERS};
+
+use vector::Vector2;
+
+use crate::args::Options;
+use crate::gamepad::{self, Gamepads};
+use crate::sound::{self, SoundOutput};
+use crate::{bmp, dl};
+
+/// Errors that can occur while running headless
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to read the input script
+    ReadScript(PathBuf, std::io::Error),
+
+    /// A line of the input script failed to parse
+    InvalidScript {
+        /// Line number (starting at 1) of the bad line
+        line: usize,
+
+        /// Why the line is invalid
+        reason: String,
+    },
+
+    /// Failed to create the output directory
+    CreateOutput(PathBuf, std::io::Error),
+
+    /// Failed to write a captured frame
+    WriteCapture(PathBuf, std::io::Error),
+
+    /// Failed to load the recorded gamepad
+    Gamepad(gamepad::Error),
+
+    /// Failed to write the game sound
+    Sound(sound::Error),
+
+    /// Failed to write the profile
+    WriteProfile(PathBuf, std::io::Error),
+
+    /// The game logic returned an error
+    Game(game_state::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ReadScript(path, err) => {
+                write!(f, "failed to read script {}: {err}", path.display())
+            }
+            Error::InvalidScript { line, reason } => write!(f, "script line {line}: {reason}"),
+            Error::CreateOutput(path, err) => {
+                write!(f, "failed to create {}: {err}", path.display())
+            }
+            Error::WriteCapture(path, err) => {
+                write!(f, "failed to write {}: {err}", path.display())
+            }
+            Error::Gamepad(err) => write!(f, "gamepad: {err}"),
+            Error::Sound(err) => write!(f, "sound: {err}"),
+            Error::WriteProfile(path, err) => {
+                write!(f, "failed to write profile {}: {err}", path.display())
+            }
+            Error::Game(err) => write!(f, "game error: {err}"),
+        }
+    }
+}
+
+/// Custom [`Result`] type for the headless platform
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A scripted action for a single frame
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Press the given button
+    Press(Button),
+
+    /// Release the given button
+    Release(Button),
+
+    /// Write the framebuffer to the given file after the frame has rendered
+    Capture(PathBuf),
+}
+
+/// An input script of commands sorted by frame
+#[derive(Debug, Default)]
+pub struct Script {
+    /// The (frame, command) pairs of the script
+    commands: Vec<(u64, Command)>,
+}
+
+impl Script {
+    /// Read and parse the [`Script`] at `path`
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Error::ReadScript(path.to_path_buf(), err))?;
+
+        Self::parse(&text)
+    }
+
+    /// Parse a [`Script`] from the given text
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut commands = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: String| Error::InvalidScript {
+                line: index + 1,
+                reason,
+            };
+
+            // Ignore comments and empty lines
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let frame = words.next().unwrap_or_default();
+            let frame = frame
+                .parse()
+                .map_err(|_| invalid(format!("invalid frame number: {frame}")))?;
+
+            let command = words.next().unwrap_or_default();
+            let Some(arg) = words.next() else {
+                return Err(invalid(format!("{command} requires an argument")));
+            };
+
+            let button =
+                || Button::from_name(arg).ok_or_else(|| invalid(format!("unknown button: {arg}")));
+
+            let command = match command {
+                "press" => Command::Press(button()?),
+                "release" => Command::Release(button()?),
+                "capture" => Command::Capture(PathBuf::from(arg)),
+                _ => return Err(invalid(format!("unknown command: {command}"))),
+            };
+
+            commands.push((frame, command));
+        }
+
+        // Commands for the same frame stay in the order they were written
+        commands.sort_by_key(|(frame, _)| *frame);
+
+        Ok(Self { commands })
+    }
+
+    /// The number of frames needed to run every command in the script
+    pub fn len(&self) -> u64 {
+        self.commands.last().map_or(0, |(frame, _)| frame + 1)
+    }
+}
+
+/// Drives the game logic for a [`Script`] without a window
+pub struct Runner<'a> {
+    /// The loaded game logic library
+    game_code: dl::GameFuncs,
+
+    /// Persistent memory for the game
+    memory: Memory,
+
+    /// Player assets specific to the direction the player is facing
+    player_assets: [&'a PlayerBitmap<'a>; variant_count::<PlayerDirection>()],
+
+    /// Background asset
+    background: &'a BitmapAsset<'a>,
+
+    /// Sound assets for each of the sound effects
+    sound_assets: [&'a SoundAsset<'a>; variant_count::<SoundEffect>()],
+
+    /// Framebuffer standing in for the X11 window
+    framebuffer: Vec<u32>,
+
+    /// Recorded gamepads replayed during the run
+    gamepads: Option<Gamepads>,
+
+    /// Where the game sound goes during the run
+    sound_output: SoundOutput,
+
+    /// Profiler the timed blocks are recorded into
+    profiler: &'static Profiler,
+
+    /// Session collecting every frame's timed blocks, and the file it is written to
+    profile: Option<(Session, PathBuf)>,
+}
+
+impl<'a> Runner<'a> {
+    /// Create a [`Runner`] from the game library, memory, and assets
+    pub fn new(
+        game_code: dl::GameFuncs,
+        memory: Memory,
+        player_assets: [&'a PlayerBitmap<'a>; variant_count::<PlayerDirection>()],
+        background: &'a BitmapAsset<'a>,
+        sound_assets: [&'a SoundAsset<'a>; variant_count::<SoundEffect>()],
+    ) -> Self {
+        Self {
+            game_code,
+            memory,
+            player_assets,
+            background,
+            sound_assets,
+            framebuffer: vec![0; usize::from(GAME_WINDOW_WIDTH) * usize::from(GAME_WINDOW_HEIGHT)],
+            gamepads: None,
+            sound_output: SoundOutput::None,
+            profiler: Profiler::leak(),
+            profile: None,
+        }
+    }
+
+    /// Replay the recorded `gamepads` during the next run
+    pub fn set_gamepads(&mut self, gamepads: Gamepads) {
+        self.gamepads = Some(gamepads);
+    }
+
+    /// Send the game sound to `sound_output` during the next run
+    pub fn set_sound_output(&mut self, sound_output: SoundOutput) {
+        self.sound_output = sound_output;
+    }
+
+    /// Profile the runs, writing the timed blocks into `path` when finished
+    pub fn set_profile(&mut self, path: PathBuf) {
+        self.profile = Some((Session::new(), path));
+    }
+
+    /// Finish the sound output, completing a WAV file, and write the profile
+    pub fn finish(self) -> Result<()> {
+        self.sound_output.finish().map_err(Error::Sound)?;
+
+        if let Some((session, path)) = &self.profile {
+            session
+                .save(path)
+                .map_err(|err| Error::WriteProfile(path.clone(), err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Run `script` for `frames` frames starting from a freshly reset game with the world
+    /// generated from `seed`.
+    ///
+    /// `capture` is called with the frame number, the requested path, and the
+    /// framebuffer for each `capture` command in the script.
+    pub fn run(
+        &mut self,
+        script: &Script,
+        frames: u64,
+        seed: u64,
+        mut capture: impl FnMut(u64, &Path, &[u32]) -> Result<()>,
+    ) -> Result<()> {
+        // Start every run from the same game memory and state
+        self.memory.reset();
+
+        // Get the reset game state
+        let mut state = game_state::State::reset(seed);
+
+        // Add a player for the keyboard
+        state.add_player(KEYBOARD_CONTROLLER);
+
+        // Current controller states for the game, with the keyboard always connected
+        let mut controllers = [Controller::default(); MAX_CONTROLLERS];
+        controllers[KEYBOARD_CONTROLLER].is_connected = true;
+
+        // Interleaved samples written each frame
+        let mut sound_samples = Vec::new();
+
+        let mut commands = script.commands.iter().peekable();
+
+        profile::set_profiler(Some(self.profiler));
+
+        for frame in 0..frames {
+            // Timed by hand so the frame is recorded before it is collected
+            let frame_block = TimedBlock::begin("frame");
+
+            for controller in &mut controllers {
+                controller
+                    .buttons
+                    .iter_mut()
+                    .for_each(ButtonState::begin_frame);
+            }
+
+            // Replay the gamepads up to the start of this frame
+            if let Some(gamepads) = &mut self.gamepads {
+                #[allow(clippy::cast_precision_loss)]
+                let now = Duration::from_secs_f32(frame as f32 / TARGET_FRAMES_PER_SECOND);
+                gamepads.poll(now, &mut controllers);
+            }
+
+            let buttons = &mut controllers[KEYBOARD_CONTROLLER].buttons;
+
+            let sound_frames = self
+                .sound_output
+                .frames_needed(SAMPLES_PER_SECOND)
+                .map_err(Error::Sound)?;
+            sound_samples.resize(sound_frames * SOUND_CHANNELS, 0);
+
+            // Apply the scripted input for this frame
+            let mut captures = Vec::new();
+            while let Some((_, command)) = commands.next_if(|(curr, _)| *curr == frame) {
+                match command {
+                    Command::Press(button) => buttons[*button as usize].process(true),
+                    Command::Release(button) => buttons[*button as usize].process(false),
+                    Command::Capture(path) => captures.push(path),
+                }
+            }
+
+            // Prepare the game state for the game logic
+            let mut game = Game {
+                framebuffer: &mut self.framebuffer,
+                width: GAME_WINDOW_WIDTH,
+                height: GAME_WINDOW_HEIGHT,
+                dt_seconds: 1.0 / TARGET_FRAMES_PER_SECOND,
+                error: Ok(()),
+                mouse: Mouse::default(),
+                controllers,
+                memory: &mut self.memory,
+                profiler: self.profiler,
+                background: self.background,
+                player_assets: self.player_assets,
+                sound_assets: self.sound_assets,
+            };
+
+            // Call the event code
+            {
+                timed_block!("game_update_and_render");
+                (self.game_code.game_update_and_render)(&mut game, &mut state);
+            }
+
+            // Get the sound for this frame
+            if game.error.is_ok() && !sound_samples.is_empty() {
+                timed_block!("game_get_sound_samples");
+                let mut sound = SoundBuffer {
+                    samples_per_second: SAMPLES_PER_SECOND,
+                    samples: &mut sound_samples,
+                };
+
+                (self.game_code.game_get_sound_samples)(&mut game, &mut state, &mut sound);
+            }
+
+            game.error.map_err(Error::Game)?;
+
+            self.sound_output
+                .write(&sound_samples)
+                .map_err(Error::Sound)?;
+
+            for path in captures {
+                capture(frame, path, &self.framebuffer)?;
+            }
+
+            drop(frame_block);
+            match &mut self.profile {
+                Some((session, _)) => session.collect(self.profiler),
+                None => self.profiler.clear(),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Run the game without a window using the given [`Options`]
+pub fn run(options: &Options) -> Result<()> {
+    let script = match &options.script {
+        Some(path) => Script::from_file(path)?,
+        None => Script::default(),
+    };
+
+    let frames = options.frames.unwrap_or_else(|| script.len());
+
+    std::fs::create_dir_all(&options.output_dir)
+        .map_err(|err| Error::CreateOutput(options.output_dir.clone(), err))?;
+
+    load_game_assets!(player_assets, background, sound_assets);
+
+    let mut runner = Runner::new(
+        dl::get_game_funcs(&options.libgame),
+        Memory::new(),
+        player_assets,
+        &background,
+        sound_assets,
+    );
+
+    if !options.gamepads.is_empty() {
+        runner.set_gamepads(Gamepads::replay(&options.gamepads).map_err(Error::Gamepad)?);
+    }
+
+    if let Some(path) = &options.wav {
+        runner.set_sound_output(SoundOutput::wav(path, SAMPLES_PER_SECOND).map_err(Error::Sound)?);
+    }
+
+    if let Some(path) = &options.profile {
+        runner.set_profile(path.clone());
+    }
+
+    let seed = options.seed();
+    println!("Seed: {seed:#x}");
+
+    runner.run(&script, frames, seed, |frame, path, framebuffer| {
+        // Write out the requested frame
+        let path = options.output_dir.join(path);
+        bmp::write(
+            &path,
+            u32::from(GAME_WINDOW_WIDTH),
+            u32::from(GAME_WINDOW_HEIGHT),
+            framebuffer,
+        )
+        .map_err(|err| Error::WriteCapture(path.clone(), err))?;
+
+        println!("Frame {frame}: captured {}", path.display());
+        Ok(())
+    })?;
+
+    runner.finish()
+}
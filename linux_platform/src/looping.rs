@@ -0,0 +1,722 @@
+//! Looped input recording and playback stored in `.hmi` files
+//!
+//! Loops are recorded into one of [`SLOT_COUNT`] independent slots, each stored in its
+//! own file (see [`slot_path`]). A loop is the [`State`] and the persistent memory at the
+//! start of the recording, followed by the frame time and the state of each button for
+//! each frame of the loop. All numbers are little endian.
+//!
+//! ```text
+//! Header
+//! 0x00  magic         [u8; 4]  b"HMI\0"
+//! 0x04  version       u32      FORMAT_VERSION
+//! 0x08  state_size    u64      size of `State` in the writing build (`STATE_SIZE`)
+//! 0x10  memory_length u64      length of the game memory (`MEMORY_LENGTH`)
+//! 0x18  button_count  u64      number of buttons stored per frame
+//! 0x20  layout_hash   u64      `game_state::LAYOUT_HASH` of the writing build
+//! 0x28  frame_count   u64      number of recorded frames
+//! 0x30  memory_offset u64      file offset of the memory snapshot
+//! 0x38  checksum      u64      FNV-1a of the state, memory, and frames
+//!
+//! Body
+//! 0x40           state   [u8; state_size]
+//! memory_offset  memory  [u8; memory_length]
+//!                frames  [Frame; frame_count]
+//!
+//! Frame
+//! 0x00  dt_seconds  f32                         measured time of the frame in seconds
+//! 0x04  buttons     [ButtonState; button_count]
+//!
+//! ButtonState
+//! 0x00  ended_down             u8  1 if the button is down at the end of the frame
+//! 0x01  half_transition_count  u8  number of times the button went up or down
+//! ```
+//!
+//! The memory snapshot starts on a [`SNAPSHOT_ALIGN`] boundary so it can be mapped
+//! directly from the file. Recording writes the snapshot straight into the mapped file
+//! and playback restores the game memory from the mapping, so restarting a loop never
+//! copies the snapshot through an intermediate buffer.
+//!
+//! The frame time is recorded since the game simulates as many fixed steps as the real
+//! time of each frame covers, so playing back the buttons alone would drift.
+//!
+//! A file is only loaded if its version, sizes, button count, and layout hash all match
+//! the running build, since the state and memory are raw copies of this build's types.
+
+use core::ffi::c_void;
+use core::mem::variant_count;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use game_state::{fnv1a, Button, ButtonState, Memory, State, FNV_OFFSET_BASIS, LAYOUT_HASH};
+use game_state::{MEMORY_LENGTH, STATE_SIZE};
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        length: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, length: usize) -> i32;
+}
+
+/// Pages can be read
+const PROT_READ: i32 = 0x1;
+
+/// Pages can be written
+const PROT_WRITE: i32 = 0x2;
+
+/// Writes to the mapping are written back to the file
+const MAP_SHARED: i32 = 0x1;
+
+/// Writes to the mapping are private to this process
+const MAP_PRIVATE: i32 = 0x2;
+
+/// Number of independent loop slots
+pub const SLOT_COUNT: usize = 4;
+
+/// Magic bytes at the start of every `.hmi` file
+const MAGIC: [u8; 4] = *b"HMI\0";
+
+/// Current version of the `.hmi` format
+const FORMAT_VERSION: u32 = 4;
+
+/// Size of the `.hmi` header
+const HEADER_SIZE: usize = 0x40;
+
+/// Alignment of the memory snapshot in the file. Mapped file offsets must be page
+/// aligned, so this covers every page size used by Linux.
+const SNAPSHOT_ALIGN: usize = 0x1_0000;
+
+/// File offset of the memory snapshot
+const MEMORY_OFFSET: usize =
+    (HEADER_SIZE + STATE_SIZE + SNAPSHOT_ALIGN - 1) & !(SNAPSHOT_ALIGN - 1);
+
+/// File offset of the recorded frames
+const FRAMES_OFFSET: usize = MEMORY_OFFSET + MEMORY_LENGTH;
+
+/// Number of buttons stored for each frame
+const BUTTON_COUNT: usize = variant_count::<Button>();
+
+/// Size of each stored [`ButtonState`]
+const BUTTON_STATE_SIZE: usize = 2;
+
+/// Size of the frame time stored for each frame
+const DT_SECONDS_SIZE: usize = 4;
+
+/// Size of the frame time and buttons stored for each frame
+const FRAME_SIZE: usize = DT_SECONDS_SIZE + BUTTON_COUNT * BUTTON_STATE_SIZE;
+
+/// Errors from reading or writing a loop recording
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to open, read, or write the file
+    Io(PathBuf, std::io::Error),
+
+    /// Failed to map the memory snapshot of the file
+    Map(PathBuf, std::io::Error),
+
+    /// The file ended before all of the data described by the header
+    Truncated(PathBuf),
+
+    /// The file is not a loop recording
+    BadMagic(PathBuf),
+
+    /// The file was written with a different version of the format
+    UnsupportedVersion(PathBuf, u32),
+
+    /// The file was written by a build with a different layout
+    IncompatibleBuild {
+        /// The recording that was refused
+        path: PathBuf,
+
+        /// The header field that doesn't match this build
+        field: &'static str,
+
+        /// The value expected by this build
+        expected: u64,
+
+        /// The value found in the file
+        found: u64,
+    },
+
+    /// The contents of the file don't match the stored checksum
+    ChecksumMismatch(PathBuf),
+
+    /// The recording has no frames to play back
+    Empty,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            Error::Map(path, err) => {
+                write!(
+                    f,
+                    "{}: failed to map memory snapshot: {err}",
+                    path.display()
+                )
+            }
+            Error::Truncated(path) => write!(f, "{}: file is truncated", path.display()),
+            Error::BadMagic(path) => write!(f, "{}: not a loop recording", path.display()),
+            Error::UnsupportedVersion(path, version) => write!(
+                f,
+                "{}: format version {version} is not supported (expected {FORMAT_VERSION})",
+                path.display()
+            ),
+            Error::IncompatibleBuild {
+                path,
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: recorded by an incompatible build ({field} is {found:#x}, this build \
+                 expects {expected:#x}), record the loop again",
+                path.display()
+            ),
+            Error::ChecksumMismatch(path) => {
+                write!(f, "{}: checksum mismatch, file is corrupt", path.display())
+            }
+            Error::Empty => write!(f, "recording has no frames"),
+        }
+    }
+}
+
+/// Custom [`Result`] type for loop recordings
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Get the file backing the given loop `slot`
+pub fn slot_path(slot: usize) -> PathBuf {
+    PathBuf::from(format!("loop_{slot}.hmi"))
+}
+
+/// Get the file a loop is recorded into before it is saved over the loop at `path`
+fn recording_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// The input of one frame of a loop
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LoopFrame {
+    /// Measured time of the frame in seconds, which decides how many steps the game
+    /// simulates
+    pub dt_seconds: f32,
+
+    /// State of each keyboard button over the frame
+    pub buttons: [ButtonState; BUTTON_COUNT],
+}
+
+/// The memory snapshot of a loop mapped from its file
+struct Snapshot {
+    /// Start of the mapping
+    addr: *mut c_void,
+}
+
+impl Snapshot {
+    /// Map the memory snapshot of `file`. Writes through a `writable` snapshot are
+    /// written back to the file.
+    fn map(file: &File, path: &Path, writable: bool) -> Result<Self> {
+        let (prot, flags) = if writable {
+            (PROT_READ | PROT_WRITE, MAP_SHARED)
+        } else {
+            (PROT_READ, MAP_PRIVATE)
+        };
+
+        // SAFETY: The file was checked or sized to hold the whole snapshot
+        let addr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                MEMORY_LENGTH,
+                prot,
+                flags,
+                file.as_raw_fd(),
+                MEMORY_OFFSET as i64,
+            )
+        };
+
+        // MAP_FAILED
+        if addr as isize == -1 {
+            return Err(Error::Map(
+                path.to_path_buf(),
+                std::io::Error::last_os_error(),
+            ));
+        }
+
+        Ok(Self { addr })
+    }
+
+    /// Get the mapped snapshot
+    fn data(&self) -> &[u8] {
+        // SAFETY: The mapping is `MEMORY_LENGTH` bytes until dropped
+        unsafe { std::slice::from_raw_parts(self.addr.cast::<u8>(), MEMORY_LENGTH) }
+    }
+
+    /// Get the mapped snapshot to write into. Only valid for writable snapshots.
+    fn data_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The mapping is `MEMORY_LENGTH` bytes until dropped
+        unsafe { std::slice::from_raw_parts_mut(self.addr.cast::<u8>(), MEMORY_LENGTH) }
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        // SAFETY: The mapping was created by `Snapshot::map` and is no longer borrowed
+        unsafe {
+            munmap(self.addr, MEMORY_LENGTH);
+        }
+    }
+}
+
+/// The state of a looping input
+pub struct LoopState {
+    /// The slot this loop is recorded in
+    pub slot: usize,
+
+    /// The file backing this loop
+    path: PathBuf,
+
+    /// The state of the game at the start of the loop
+    game_state: State,
+
+    /// The state of memory to start the loop, mapped from the loop file
+    memory: Snapshot,
+
+    /// The index for the next input frame
+    pub input_index: usize,
+
+    /// The input of each frame of the loop
+    frames: Vec<LoopFrame>,
+}
+
+/// Read a little endian `u64` at `offset` of the header
+fn header_u64(header: &[u8; HEADER_SIZE], offset: usize) -> u64 {
+    u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap())
+}
+
+/// Get the raw bytes of a game state
+fn state_bytes(state: &State) -> &[u8] {
+    // SAFETY: `State` is `Copy` plain old data of `STATE_SIZE` bytes
+    unsafe { std::slice::from_raw_parts((state as *const State).cast::<u8>(), STATE_SIZE) }
+}
+
+/// Get the checksum of the body of a loop file
+fn checksum(state: &[u8], memory: &[u8], frames: &[u8]) -> u64 {
+    let hash = fnv1a(FNV_OFFSET_BASIS, state);
+    let hash = fnv1a(hash, memory);
+    fnv1a(hash, frames)
+}
+
+impl LoopState {
+    /// Begin recording a new loop into `slot` starting from the given state and memory.
+    /// The loop already in the slot is kept until the recording is saved.
+    pub fn record(slot: usize, game_state: State, memory: &Memory) -> Result<Self> {
+        Self::record_to(slot_path(slot), slot, game_state, memory.data())
+    }
+
+    /// Begin recording a new loop into the file at `path` starting from the given state
+    /// and `memory` data
+    fn record_to(path: PathBuf, slot: usize, game_state: State, memory: &[u8]) -> Result<Self> {
+        // Record next to the loop file so an unsaved recording never replaces it
+        let recording_path = recording_path(&path);
+        let io_err = |err| Error::Io(recording_path.clone(), err);
+
+        // The file is mapped for reading and writing, so it must be opened for both
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&recording_path)
+            .map_err(io_err)?;
+        file.set_len(FRAMES_OFFSET as u64).map_err(io_err)?;
+
+        // Snapshot the memory directly into the loop file
+        let mut snapshot = Snapshot::map(&file, &recording_path, true)?;
+        snapshot.data_mut().copy_from_slice(memory);
+
+        Ok(Self {
+            slot,
+            path,
+            game_state,
+            memory: snapshot,
+            input_index: 0,
+            frames: Vec::with_capacity(256),
+        })
+    }
+
+    /// Add the input for the next frame of a recording
+    pub fn push_input(&mut self, frame: LoopFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Get the input for the next frame of the loop
+    pub fn next_input(&mut self) -> LoopFrame {
+        let index = self.input_index;
+        self.input_index = (self.input_index + 1) % self.frames.len();
+        self.frames[index]
+    }
+
+    /// Restore the game memory to the start of the loop and return the starting state
+    pub fn restore(&self, memory: &mut Memory) -> State {
+        memory.restore(self.memory.data());
+        self.game_state
+    }
+
+    /// Finish a recording by writing the state, frames, and header to the recording file,
+    /// then moving it over the loop file
+    pub fn save(&self) -> Result<()> {
+        let recording_path = recording_path(&self.path);
+
+        if self.frames.is_empty() {
+            let _ = std::fs::remove_file(&recording_path);
+            return Err(Error::Empty);
+        }
+
+        let io_err = |err| Error::Io(recording_path.clone(), err);
+
+        let state = state_bytes(&self.game_state);
+        let mut frames = Vec::with_capacity(self.frames.len() * FRAME_SIZE);
+        for frame in &self.frames {
+            frames.extend(frame.dt_seconds.to_le_bytes());
+            for button in &frame.buttons {
+                frames.extend([u8::from(button.ended_down), button.half_transition_count]);
+            }
+        }
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend(MAGIC);
+        header.extend(FORMAT_VERSION.to_le_bytes());
+        header.extend((STATE_SIZE as u64).to_le_bytes());
+        header.extend((MEMORY_LENGTH as u64).to_le_bytes());
+        header.extend((BUTTON_COUNT as u64).to_le_bytes());
+        header.extend(LAYOUT_HASH.to_le_bytes());
+        header.extend((self.frames.len() as u64).to_le_bytes());
+        header.extend((MEMORY_OFFSET as u64).to_le_bytes());
+        header.extend(checksum(state, self.memory.data(), &frames).to_le_bytes());
+        assert_eq!(header.len(), HEADER_SIZE);
+
+        // The memory snapshot was already written through the mapping
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&recording_path)
+            .map_err(io_err)?;
+        file.write_all(&header).map_err(io_err)?;
+        file.write_all(state).map_err(io_err)?;
+        file.seek(SeekFrom::Start(FRAMES_OFFSET as u64))
+            .map_err(io_err)?;
+        file.write_all(&frames).map_err(io_err)?;
+        file.set_len((FRAMES_OFFSET + frames.len()) as u64)
+            .map_err(io_err)?;
+
+        // Replace the previous loop only once the recording is complete. The snapshot
+        // stays mapped through the rename, so the recording can be played back directly.
+        std::fs::rename(&recording_path, &self.path)
+            .map_err(|err| Error::Io(self.path.clone(), err))
+    }
+
+    /// Load the loop recorded in `slot`, refusing loops written by an incompatible build
+    pub fn load(slot: usize) -> Result<Self> {
+        Self::load_from(slot_path(slot), slot)
+    }
+
+    /// Load the loop in the file at `path` as the loop in `slot`
+    fn load_from(path: PathBuf, slot: usize) -> Result<Self> {
+        let io_err = |err: std::io::Error| {
+            if err.kind() == std::io::ErrorKind::UnexpectedEof {
+                Error::Truncated(path.clone())
+            } else {
+                Error::Io(path.clone(), err)
+            }
+        };
+
+        let mut file = File::open(&path).map_err(io_err)?;
+        let file_len = file.metadata().map_err(io_err)?.len();
+
+        let mut header = [0u8; HEADER_SIZE];
+        file.read_exact(&mut header).map_err(io_err)?;
+
+        if header[..4] != MAGIC {
+            return Err(Error::BadMagic(path));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(path, version));
+        }
+
+        // Refuse anything that doesn't match the layout of this build
+        for (offset, field, expected) in [
+            (0x08, "state size", STATE_SIZE as u64),
+            (0x10, "memory length", MEMORY_LENGTH as u64),
+            (0x18, "button count", BUTTON_COUNT as u64),
+            (0x20, "layout hash", LAYOUT_HASH),
+            (0x30, "memory offset", MEMORY_OFFSET as u64),
+        ] {
+            let found = header_u64(&header, offset);
+            if found != expected {
+                return Err(Error::IncompatibleBuild {
+                    path,
+                    field,
+                    expected,
+                    found,
+                });
+            }
+        }
+
+        let frame_count = header_u64(&header, 0x28);
+        let expected_checksum = header_u64(&header, 0x38);
+
+        if frame_count == 0 {
+            return Err(Error::Empty);
+        }
+
+        // Check the file holds everything the header describes before mapping it
+        let expected_len = frame_count
+            .checked_mul(FRAME_SIZE as u64)
+            .and_then(|len| len.checked_add(FRAMES_OFFSET as u64));
+        if expected_len.is_none_or(|len| file_len < len) {
+            return Err(Error::Truncated(path));
+        }
+
+        let mut game_state = vec![0u8; STATE_SIZE];
+        file.read_exact(&mut game_state).map_err(io_err)?;
+
+        let frame_count = usize::try_from(frame_count).unwrap();
+        let mut frames = vec![0u8; frame_count * FRAME_SIZE];
+        file.seek(SeekFrom::Start(FRAMES_OFFSET as u64))
+            .map_err(io_err)?;
+        file.read_exact(&mut frames).map_err(io_err)?;
+
+        let memory = Snapshot::map(&file, &path, false)?;
+
+        if checksum(&game_state, memory.data(), &frames) != expected_checksum {
+            return Err(Error::ChecksumMismatch(path));
+        }
+
+        // SAFETY: The state bytes were written by a build with the same layout and
+        // verified by the checksum
+        let game_state = unsafe { std::ptr::read_unaligned(game_state.as_ptr().cast::<State>()) };
+
+        let frames = frames
+            .chunks_exact(FRAME_SIZE)
+            .map(|frame| {
+                let (dt_seconds, buttons) = frame.split_at(DT_SECONDS_SIZE);
+                LoopFrame {
+                    dt_seconds: f32::from_le_bytes(dt_seconds.try_into().unwrap()),
+                    buttons: std::array::from_fn(|index| {
+                        let button = &buttons[index * BUTTON_STATE_SIZE..][..BUTTON_STATE_SIZE];
+                        ButtonState {
+                            ended_down: button[0] != 0,
+                            half_transition_count: button[1],
+                        }
+                    }),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            slot,
+            path,
+            game_state,
+            memory,
+            input_index: 0,
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get a path in the temp directory for the loop file of the test called `name`
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hmi_{name}_{}.hmi", std::process::id()))
+    }
+
+    /// Get a frame with a distinct frame time and buttons for `index`
+    fn test_frame(index: u8) -> LoopFrame {
+        let mut buttons = [ButtonState::default(); BUTTON_COUNT];
+        buttons[usize::from(index) % BUTTON_COUNT] = ButtonState {
+            ended_down: true,
+            half_transition_count: index,
+        };
+
+        LoopFrame {
+            dt_seconds: 1.0 / (30.0 + f32::from(index)),
+            buttons,
+        }
+    }
+
+    /// Record and save a loop of `frames` frames into the file for the test called `name`
+    fn save_test_loop(name: &str, frames: u8) -> PathBuf {
+        let path = test_path(name);
+        let memory = (0..MEMORY_LENGTH)
+            .map(|index| index as u8)
+            .collect::<Vec<_>>();
+
+        let mut recording = LoopState::record_to(path.clone(), 2, State::reset(7), &memory)
+            .expect("failed to start recording");
+        for index in 0..frames {
+            recording.push_input(test_frame(index));
+        }
+        recording.save().expect("failed to save recording");
+
+        path
+    }
+
+    /// Overwrite the bytes at `offset` of the file at `path` with `bytes`
+    fn patch(path: &Path, offset: u64, bytes: &[u8]) {
+        let mut file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    /// Load the loop file at `path`, then remove it
+    fn load_and_remove(path: &Path) -> Result<LoopState> {
+        let res = LoopState::load_from(path.to_path_buf(), 2);
+        let _ = std::fs::remove_file(path);
+        res
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = save_test_loop("round_trip", 5);
+        let mut loaded = load_and_remove(&path).expect("failed to load recording");
+
+        assert_eq!(loaded.slot, 2);
+        assert_eq!(loaded.game_state.seed, 7);
+        assert_eq!(loaded.game_state.rng, State::reset(7).rng);
+        assert!(loaded
+            .memory
+            .data()
+            .iter()
+            .enumerate()
+            .all(|(index, byte)| *byte == index as u8));
+
+        // Playback wraps around to the first frame
+        for index in [0, 1, 2, 3, 4, 0] {
+            assert_eq!(loaded.next_input(), test_frame(index));
+        }
+    }
+
+    #[test]
+    fn empty_recording() {
+        let path = test_path("empty_recording");
+        let memory = vec![0; MEMORY_LENGTH];
+        let recording = LoopState::record_to(path.clone(), 0, State::reset(0), &memory).unwrap();
+
+        assert!(matches!(recording.save(), Err(Error::Empty)));
+        assert!(!path.exists());
+        assert!(!recording_path(&path).exists());
+    }
+
+    #[test]
+    fn unsaved_recording_keeps_loop() {
+        let path = save_test_loop("unsaved_recording_keeps_loop", 2);
+
+        // Abandon a recording over the saved loop
+        let memory = vec![0; MEMORY_LENGTH];
+        let mut recording =
+            LoopState::record_to(path.clone(), 2, State::reset(9), &memory).unwrap();
+        recording.push_input(test_frame(7));
+        drop(recording);
+
+        let _ = std::fs::remove_file(recording_path(&path));
+        let loaded = load_and_remove(&path).expect("saved loop was lost");
+        assert_eq!(loaded.frames, [test_frame(0), test_frame(1)]);
+    }
+
+    #[test]
+    fn saved_recording_replaces_loop() {
+        let path = save_test_loop("saved_recording_replaces_loop", 2);
+
+        let memory = vec![0; MEMORY_LENGTH];
+        let mut recording =
+            LoopState::record_to(path.clone(), 2, State::reset(9), &memory).unwrap();
+        recording.push_input(test_frame(7));
+        recording.save().unwrap();
+        assert!(!recording_path(&path).exists());
+
+        let loaded = load_and_remove(&path).unwrap();
+        assert_eq!(loaded.game_state.seed, 9);
+        assert_eq!(loaded.frames, [test_frame(7)]);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let path = save_test_loop("bad_magic", 1);
+        patch(&path, 0, b"RIFF");
+        assert!(matches!(load_and_remove(&path), Err(Error::BadMagic(_))));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let path = save_test_loop("unsupported_version", 1);
+        patch(&path, 0x04, &(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            load_and_remove(&path),
+            Err(Error::UnsupportedVersion(_, version)) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn layout_hash_mismatch() {
+        let path = save_test_loop("layout_hash_mismatch", 1);
+        patch(&path, 0x20, &(!LAYOUT_HASH).to_le_bytes());
+        assert!(matches!(
+            load_and_remove(&path),
+            Err(Error::IncompatibleBuild { field: "layout hash", found, .. }) if found == !LAYOUT_HASH
+        ));
+    }
+
+    #[test]
+    fn truncated_header() {
+        let path = save_test_loop("truncated_header", 1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(HEADER_SIZE as u64 - 1)
+            .unwrap();
+        assert!(matches!(load_and_remove(&path), Err(Error::Truncated(_))));
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let path = save_test_loop("truncated_frames", 3);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len((FRAMES_OFFSET + 3 * FRAME_SIZE - 1) as u64)
+            .unwrap();
+        assert!(matches!(load_and_remove(&path), Err(Error::Truncated(_))));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let path = save_test_loop("checksum_mismatch", 3);
+
+        // Flip a bit of the last button of the last frame
+        let offset = (FRAMES_OFFSET + 3 * FRAME_SIZE - 1) as u64;
+        let mut byte = [0];
+        let mut file = File::open(&path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+        patch(&path, offset, &[byte[0] ^ 1]);
+
+        assert!(matches!(
+            load_and_remove(&path),
+            Err(Error::ChecksumMismatch(_))
+        ));
+    }
+}
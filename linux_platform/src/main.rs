@@ -3,14 +3,26 @@
 #![feature(variant_count)]
 
 use core::mem::variant_count;
-use std::io::{Read, Write};
 
+mod args;
+mod bindings;
+mod bmp;
 mod dl;
-use game_state::{BitmapAsset, Button, Game, Memory, GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
-use game_state::{PlayerBitmap, PlayerDirection, MEMORY_LENGTH, STATE_SIZE};
-use game_state::{MEMORY_BASE_ADDR, MILLISECONDS_PER_FRAME};
+mod watch;
+use game_state::{
+    BitmapAsset, Button, ButtonState, Game, Memory, GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH,
+};
+use game_state::{Controller, Mouse, MouseButton, PlayerBitmap, PlayerDirection};
+use game_state::{SoundAsset, SoundBuffer, SoundEffect, SAMPLES_PER_SECOND, SOUND_CHANNELS};
+use game_state::{KEYBOARD_CONTROLLER, MAX_CONTROLLERS, TARGET_FRAMES_PER_SECOND};
+
+use game_state::profile::{self, Session, TimedBlock};
+use game_state::{timed_block, Profiler};
+
+use std::time::{Duration, Instant};
 
 use vector::Vector2;
+use x11_rs::Modifiers;
 
 /// Loads the front/left/right/back player assets from `assets/early_data/test/test_hero_`
 macro_rules! load_asset {
@@ -37,129 +49,139 @@ macro_rules! load_asset {
     };
 }
 
-/// The state of a looping input
-struct LoopState {
-    /// The state of the game at the start of the loop
-    game_state: game_state::State,
+/// Loads the `$name` sound asset from `assets/early_data/test/test_$name.wav`
+macro_rules! load_sound {
+    ($name:ident) => {
+        let path = concat!("assets/early_data/test/test_", stringify!($name), ".wav");
+        let $name = std::fs::read(path)
+            .unwrap_or_else(|err| panic!("Failed to read sound asset {path}: {err}"));
+        let $name = SoundAsset::from_data(&$name)
+            .unwrap_or_else(|err| panic!("Invalid sound asset {path}: {err}"));
+    };
+}
+
+/// Loads the player assets array, the background asset, and the sound assets array into
+/// `$player_assets`, `$background`, and `$sound_assets`
+macro_rules! load_game_assets {
+    ($player_assets:ident, $background:ident, $sound_assets:ident) => {
+        // Load the player assets
+        load_asset!(front);
+        load_asset!(left);
+        load_asset!(right);
+        load_asset!(back);
+
+        // Set the assets into the player assets array
+        let mut $player_assets = [&front, &front, &front, &front];
+        $player_assets[PlayerDirection::Front as usize] = &front;
+        $player_assets[PlayerDirection::Back as usize] = &back;
+        $player_assets[PlayerDirection::Left as usize] = &left;
+        $player_assets[PlayerDirection::Right as usize] = &right;
+
+        let $background = std::fs::read("assets/early_data/test/test_background.bmp")
+            .expect("Failed to read background asset");
+        let $background = BitmapAsset::from_data(&$background);
+
+        // Load the sound assets for each sound effect
+        load_sound!(footstep);
+        load_sound!(bump);
+
+        let mut $sound_assets = [&footstep, &footstep];
+        $sound_assets[SoundEffect::Footstep as usize] = &footstep;
+        $sound_assets[SoundEffect::Bump as usize] = &bump;
+    };
+}
 
-    /// The state of memory to start the loop
-    memory: Vec<u8>,
+mod gamepad;
+mod golden;
+mod headless;
+mod looping;
+mod sound;
+mod wav;
 
-    /// The index for the next input buttons
-    input_index: usize,
+use bindings::{Action, ActionMap, Input};
+use gamepad::Gamepads;
+use looping::{LoopFrame, LoopState};
+use sound::SoundOutput;
 
-    /// The button sequence of the loop
-    buttons: Vec<[bool; variant_count::<Button>()]>,
+enum GameplayState {
+    Normal,
+    LoopRecording(LoopState),
+    LoopPlayback(LoopState),
 }
 
-impl LoopState {
-    pub fn next_input(&mut self) -> [bool; variant_count::<Button>()] {
-        let index = self.input_index;
-        self.input_index = (self.input_index + 1) % self.buttons.len();
-        self.buttons[index]
+/// Time before the end of a frame to stop sleeping and spin instead, covering how late
+/// the scheduler can wake the thread
+const SPIN_TIME: Duration = Duration::from_micros(1500);
+
+/// Wait until `frame_time` has passed since `frame_start`. Sleeps for most of the wait
+/// and spins for the rest, since sleeping alone can overshoot by over a millisecond.
+fn wait_for_frame_end(frame_start: Instant, frame_time: Duration) {
+    timed_block!("wait_for_frame_end");
+
+    let remaining = frame_time.saturating_sub(frame_start.elapsed());
+    if remaining > SPIN_TIME {
+        std::thread::sleep(remaining - SPIN_TIME);
     }
 
-    pub fn write_to_disk(&self, filename: &str) {
-        // Open the file to write
-        let mut file = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(filename)
-            .expect("Failed to open loop state file");
-
-        // Write the raw data slice to the file
-        let game_state_bytes = unsafe {
-            std::slice::from_raw_parts(
-                (&self.game_state as *const game_state::State) as *const u8,
-                STATE_SIZE,
-            )
-        };
-        file.write(&game_state_bytes).unwrap();
-
-        // Write the data len to the file
-        // [ len u64 ][ memory bytes]
-        let data_len = self.memory.len() as u64;
-        file.write(&data_len.to_le_bytes()).unwrap();
-        assert!(self.memory.len() == MEMORY_LENGTH);
-        file.write(self.memory.as_slice()).unwrap();
-
-        // Write the buttons to the file
-        // [ len u64 ][ button bytes]
-        let num_buttons = self.buttons.len() as u64;
-        file.write(&num_buttons.to_le_bytes()).unwrap();
-
-        for buttons in &self.buttons {
-            for button in buttons {
-                file.write(&[*button as u8]).unwrap();
-            }
-        }
+    while frame_start.elapsed() < frame_time {
+        std::hint::spin_loop();
     }
+}
 
-    // Read a saved loop from disk
-    pub fn read_from_disk(&self, filename: &str) -> Self {
-        let mut file = std::fs::OpenOptions::new()
-            .read(true)
-            .open(filename)
-            .expect("Failed to open loop state file");
-
-        // Read the game state from the file
-        let mut game_state_data = [0u8; STATE_SIZE];
-        file.read(&mut game_state_data)
-            .expect("Failed to read loop game state");
-
-        // Raw data case back to the game state
-        let game_state = unsafe { *game_state_data.as_ptr().cast::<game_state::State>() };
-
-        // Read the memory length
-        let mut memory_len = [0u8; 8];
-        file.read(&mut memory_len)
-            .expect("Failed to read size of loop memory");
-        let memory_len = u64::from_le_bytes(memory_len);
-        assert!(
-            memory_len as usize == MEMORY_LENGTH,
-            "Looping memory different than game memory"
-        );
+/// Get the game mouse button for the given window mouse button
+fn mouse_button(button: x11_rs::MouseButton) -> Option<MouseButton> {
+    match button {
+        x11_rs::MouseButton::Left => Some(MouseButton::Left),
+        x11_rs::MouseButton::Middle => Some(MouseButton::Middle),
+        x11_rs::MouseButton::Right => Some(MouseButton::Right),
+        _ => None,
+    }
+}
 
-        // Read the memory bytes from disk
-        let mut memory = vec![0u8; memory_len as usize];
-        file.read(memory.as_mut_slice())
-            .expect("Failed to read loop memory");
-
-        // Read the number of buttons
-        let mut num_buttons = [0u8; 8];
-        file.read(&mut num_buttons)
-            .expect("Failed to read number of loop buttons");
-        let num_buttons = u64::from_le_bytes(num_buttons);
-
-        // Read the buttons from the file
-        let mut buttons = vec![[false; variant_count::<Button>()]; num_buttons as usize];
-        for curr_buttons in buttons.iter_mut() {
-            let mut tmp_buttons = [0u8; 6];
-            file.read(&mut tmp_buttons)
-                .expect("Failed to read loop buttons");
-
-            tmp_buttons
-                .iter_mut()
-                .enumerate()
-                .for_each(|(i, x)| curr_buttons[i] = *x != 0);
+fn main() {
+    let options = match args::Options::from_env() {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("{err}");
+            }
+
+            eprintln!("{}", args::USAGE);
+            std::process::exit(1);
         }
+    };
 
-        Self {
-            game_state,
-            memory,
-            input_index: 0,
-            buttons,
+    // Check the golden images if requested
+    if let Some(golden_dir) = &options.golden {
+        match golden::run(golden_dir, &options) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                eprintln!("Golden: {err}");
+                std::process::exit(1);
+            }
         }
     }
-}
 
-enum GameplayState {
-    Normal,
-    LoopRecording,
-    LoopPlayback,
-}
+    // Run without a window if requested
+    if options.headless {
+        if let Err(err) = headless::run(&options) {
+            eprintln!("Headless: {err}");
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    // Bindings from inputs to game buttons and platform actions
+    let mut action_map = match ActionMap::load(&options.bindings) {
+        Ok(action_map) => action_map,
+        Err(err) => {
+            eprintln!("Bindings: {err}");
+            std::process::exit(1);
+        }
+    };
 
-fn main() {
     let mut window = x11_rs::SimpleWindow::build()
         .x(0)
         .y(0)
@@ -168,146 +190,269 @@ fn main() {
         .border_width(1)
         .border(0)
         .background(1)
+        .scaling(options.scaling)
+        .title("Handmade Ferris")
+        .shared_memory(options.shared_memory)
         .finish()
         .expect("Failed to create X11 simple window");
 
-    window.create_image();
+    if window.is_shared_memory() {
+        println!("Presenting frames through MIT-SHM");
+    } else {
+        println!("Presenting frames through XPutImage");
+    }
 
     window.put_image();
 
-    // Load the game logic library
-    let mut game_code = dl::get_game_funcs();
+    // Load the game logic library and watch for it to be rebuilt
+    let mut game_code = dl::get_game_funcs(&options.libgame);
+    let mut library_watcher = watch::LibraryWatcher::new(&options.libgame)
+        .expect("Failed to watch the game library for hot reload");
     let mut game_update_and_render;
 
-    let time_begin = std::time::Instant::now();
+    let time_begin = Instant::now();
+
+    // Target duration of each frame
+    let frame_time = Duration::from_secs_f32(1.0 / TARGET_FRAMES_PER_SECOND);
 
     // Get the reset game state
-    let mut state = game_state::State::reset();
+    let seed = options.seed();
+    println!("Seed: {seed:#x}");
+    let mut state = game_state::State::reset(seed);
 
     // Add a player
-    state.add_player();
-
-    // Current button states for the game
-    let mut buttons = [false; variant_count::<Button>()];
+    state.add_player(KEYBOARD_CONTROLLER);
+
+    // Current keyboard button states for the game
+    let mut buttons = [ButtonState::default(); variant_count::<Button>()];
+
+    // Current mouse state for the game
+    let mut mouse = Mouse::default();
+
+    // Current gamepad controller states for the game, from recordings if requested
+    let mut controllers = [Controller::default(); MAX_CONTROLLERS];
+    let mut gamepads = if options.gamepads.is_empty() {
+        Gamepads::detect()
+    } else {
+        Gamepads::replay(&options.gamepads).unwrap_or_else(|err| {
+            eprintln!("Gamepad: {err}");
+            std::process::exit(1);
+        })
+    };
 
     // Persistent memory for the game
     let mut memory = Memory::new();
 
-    // Load the player assets
-    load_asset!(front);
-    load_asset!(left);
-    load_asset!(right);
-    load_asset!(back);
+    load_game_assets!(player_assets, background, sound_assets);
 
-    // Set the assets into the player assets array
-    let mut player_assets = [&front, &front, &front, &front];
-    player_assets[PlayerDirection::Front as usize] = &front;
-    player_assets[PlayerDirection::Back as usize] = &back;
-    player_assets[PlayerDirection::Left as usize] = &left;
-    player_assets[PlayerDirection::Right as usize] = &right;
+    // Play the game sound, or write it into a WAV file if requested
+    let mut sound_output = match &options.wav {
+        Some(path) => SoundOutput::wav(path, SAMPLES_PER_SECOND).unwrap_or_else(|err| {
+            eprintln!("Sound: {err}");
+            std::process::exit(1);
+        }),
+        None => SoundOutput::alsa(SAMPLES_PER_SECOND),
+    };
 
-    let background = std::fs::read("assets/early_data/test/test_background.bmp")
-        .expect("Failed to read background asset");
-    let background = BitmapAsset::from_data(&background);
+    // Interleaved samples queued each frame
+    let mut sound_samples = Vec::new();
 
     let mut looping = GameplayState::Normal;
 
-    let mut looping_state = LoopState {
-        game_state: state.clone(),
-        memory: Vec::new(),
-        buttons: Vec::with_capacity(256),
-        input_index: 0,
-    };
+    // The loop slot used by the next recording or playback
+    let mut loop_slot = 1;
 
-    // Main event loop
-    for frame in 0.. {
-        // Begin the timer for this loop iteration
-        let frame_start = std::time::Instant::now();
+    // Generation of the game library that returned an error. The simulation is paused
+    // until a new library is loaded.
+    let mut failed_library = None;
+
+    // Start of the previous frame, used to measure the frame delta
+    let mut previous_frame_start = None;
+
+    // Timed blocks are recorded every frame, but only kept when profiling
+    let profiler = Profiler::leak();
+    profile::set_profiler(Some(profiler));
+    let mut session = options.profile.as_ref().map(|_| Session::new());
 
-        game_code = game_code.reload();
+    // Main event loop
+    'frames: for frame in 0.. {
+        // Begin the timer for this loop iteration, measuring the real time since the
+        // previous frame began
+        let frame_start = Instant::now();
+        let mut dt_seconds = previous_frame_start
+            .map_or(frame_time, |previous| frame_start - previous)
+            .as_secs_f32();
+        previous_frame_start = Some(frame_start);
+
+        // Timed by hand so the frame is recorded before it is collected
+        let frame_block = TimedBlock::begin("frame");
+
+        game_code = game_code.reload(&mut library_watcher);
         game_update_and_render = &game_code.game_update_and_render;
 
-        // Get the next event from X11
-        let event = window.check_event();
-
-        // Event handler loop
-        match event {
-            Some(x11_rs::Event::KeyPress(key)) => {
-                let button = match key {
-                    'w' => Some(Button::Up),
-                    'a' => Some(Button::Left),
-                    's' => Some(Button::Down),
-                    'd' => Some(Button::Right),
-                    'n' => Some(Button::DecreaseSpeed),
-                    'm' => Some(Button::IncreaseSpeed),
-                    _ => None,
-                };
+        // Resume the simulation once the failed game library has been replaced
+        if failed_library.is_some_and(|generation| generation != game_code.generation) {
+            println!("Game library reloaded, resuming");
+            failed_library = None;
+        }
 
-                if let Some(button) = button {
-                    buttons[button as usize] = true;
+        // Transitions are counted per frame
+        for button in buttons.iter_mut().chain(&mut mouse.buttons).chain(
+            controllers
+                .iter_mut()
+                .flat_map(|controller| &mut controller.buttons),
+        ) {
+            button.begin_frame();
+        }
+
+        gamepads.poll(time_begin.elapsed(), &mut controllers);
+
+        // Handle every pending event from X11
+        while let Some(event) = window.check_event() {
+            // Get the bindable input from the event
+            let (input, modifiers, is_down) = match event {
+                x11_rs::Event::KeyPress(key, modifiers) => (Input::Key(key), modifiers, true),
+                x11_rs::Event::KeyRelease(key, modifiers) => (Input::Key(key), modifiers, false),
+                x11_rs::Event::MouseDown(button) => {
+                    if let Some(button) = mouse_button(button) {
+                        mouse.buttons[button as usize].process(true);
+                    }
+
+                    (Input::Mouse(button), Modifiers::default(), true)
                 }
-            }
-            Some(x11_rs::Event::KeyRelease(key)) => {
-                let button = match key {
-                    'w' => Some(Button::Up),
-                    'a' => Some(Button::Left),
-                    's' => Some(Button::Down),
-                    'd' => Some(Button::Right),
-                    'n' => Some(Button::DecreaseSpeed),
-                    'm' => Some(Button::IncreaseSpeed),
-                    'p' => {
-                        // Play a recording from disk
-                        looping_state = looping_state.read_from_disk("loop.hmi");
-                        looping = GameplayState::LoopPlayback;
-                        None
+                x11_rs::Event::MouseUp(button) => {
+                    if let Some(button) = mouse_button(button) {
+                        mouse.buttons[button as usize].process(false);
                     }
-                    'l' => {
-                        // Normal -> Recording -> Playback -> Normal
-                        match looping {
-                            GameplayState::Normal => {
-                                println!("Loop: recording..");
-
-                                // Initialize the loop state
-                                looping_state = LoopState {
-                                    game_state: state.clone(),
-                                    memory: memory.data_as_vec(),
-                                    buttons: Vec::with_capacity(256),
-                                    input_index: 0,
-                                };
-
-                                // Goto the recording state
-                                looping = GameplayState::LoopRecording;
-                            }
-                            GameplayState::LoopRecording => {
-                                // Goto the playback state
-                                println!("Loop: playback..");
 
-                                looping_state.write_to_disk("loop.hmi");
-                                looping_state = looping_state.read_from_disk("loop.hmi");
+                    (Input::Mouse(button), Modifiers::default(), false)
+                }
+                x11_rs::Event::MouseMove { x, y } => {
+                    // Map the cursor from the window into the scaled framebuffer
+                    (mouse.x, mouse.y) = window.to_framebuffer(x, y);
+                    continue;
+                }
+                x11_rs::Event::MouseWheel(wheel) => {
+                    match wheel {
+                        x11_rs::Wheel::Up => mouse.wheel += 1,
+                        x11_rs::Wheel::Down => mouse.wheel -= 1,
+                        x11_rs::Wheel::Left | x11_rs::Wheel::Right => {}
+                    }
 
-                                looping = GameplayState::LoopPlayback;
-                            }
-                            GameplayState::LoopPlayback => {
-                                // Goto the normal state
-                                println!("Loop: stop..");
-                                looping = GameplayState::Normal;
-                                buttons = [false; variant_count::<Button>()];
-                            }
+                    continue;
+                }
+                x11_rs::Event::Resize { width, height } => {
+                    window.resize(width, height);
+                    continue;
+                }
+                x11_rs::Event::Close => break 'frames,
+                x11_rs::Event::FocusOut => {
+                    // Keys released while unfocused are never reported, so release
+                    // everything rather than leaving buttons stuck down
+                    for button in buttons.iter_mut().chain(&mut mouse.buttons) {
+                        button.process(false);
+                    }
+
+                    continue;
+                }
+                x11_rs::Event::Unknown(val) => {
+                    println!("Unknown event: {}", val);
+                    continue;
+                }
+                x11_rs::Event::Expose | x11_rs::Event::FocusIn => continue,
+            };
+
+            if is_down {
+                // Game buttons are held while their input is held
+                if let Some(Action::Button(button)) = action_map.action(input, modifiers) {
+                    buttons[button as usize].process(true);
+                }
+
+                continue;
+            }
+
+            // Release every button bound to the input, even if the modifiers or the
+            // active contexts changed while it was held
+            for button in action_map.buttons(input) {
+                buttons[button as usize].process(false);
+            }
+
+            // Platform actions trigger once the input is released
+            match action_map.action(input, modifiers) {
+                Some(Action::Fullscreen) => window.toggle_fullscreen(),
+                Some(Action::Toggle(context)) => {
+                    action_map.toggle(context);
+                    let status = if action_map.is_active(context) {
+                        "on"
+                    } else {
+                        "off"
+                    };
+                    println!("Bindings: {context} {status}");
+                }
+                Some(Action::LoopSlot(slot)) => {
+                    // Select the loop slot for the next recording or playback
+                    println!("Loop: slot {slot} selected");
+                    loop_slot = slot;
+                }
+                Some(Action::LoopPlay) => {
+                    // Save a recording in progress first rather than discarding it
+                    if let GameplayState::LoopRecording(recording) = &looping {
+                        match recording.save() {
+                            Ok(()) => println!("Loop: saved slot {}", recording.slot),
+                            Err(err) => println!("Loop: {err}"),
                         }
 
-                        None
+                        looping = GameplayState::Normal;
                     }
-                    _ => None,
-                };
 
-                if let Some(button) = button {
-                    buttons[button as usize] = false;
+                    // Play the recording in the selected slot from disk
+                    match LoopState::load(loop_slot) {
+                        Ok(loaded) => {
+                            println!("Loop: playback slot {loop_slot}..");
+                            looping = GameplayState::LoopPlayback(loaded);
+                        }
+                        Err(err) => println!("Loop: {err}"),
+                    }
                 }
+                Some(Action::LoopRecord) => {
+                    // Normal -> Recording -> Playback -> Normal
+                    looping = match std::mem::replace(&mut looping, GameplayState::Normal) {
+                        GameplayState::Normal => {
+                            println!("Loop: recording slot {loop_slot}..");
+
+                            // Snapshot the current state into the selected slot
+                            match LoopState::record(loop_slot, state, &memory) {
+                                Ok(recording) => GameplayState::LoopRecording(recording),
+                                Err(err) => {
+                                    println!("Loop: {err}");
+                                    GameplayState::Normal
+                                }
+                            }
+                        }
+                        GameplayState::LoopRecording(recording) => {
+                            // Save the recording and play it back, falling back to
+                            // normal play if it can't be written
+                            match recording.save() {
+                                Ok(()) => {
+                                    println!("Loop: playback slot {}..", recording.slot);
+                                    GameplayState::LoopPlayback(recording)
+                                }
+                                Err(err) => {
+                                    println!("Loop: {err}");
+                                    GameplayState::Normal
+                                }
+                            }
+                        }
+                        GameplayState::LoopPlayback(_) => {
+                            // Goto the normal state
+                            println!("Loop: stop..");
+                            buttons = [ButtonState::default(); variant_count::<Button>()];
+                            GameplayState::Normal
+                        }
+                    };
+                }
+                Some(Action::Button(_)) | None => {}
             }
-            Some(x11_rs::Event::Unknown(val)) => {
-                println!("Unknown event: {}", val);
-            }
-            Some(x11_rs::Event::Expose) | None => {}
         }
 
         // Debug print the frames per second
@@ -319,71 +464,154 @@ fn main() {
             );
         }
 
-        match looping {
-            GameplayState::LoopRecording => {
-                looping_state.buttons.push(buttons.clone());
+        // Top up the queued sound
+        let sound_frames = match sound_output.frames_needed(SAMPLES_PER_SECOND) {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("Sound: {err}, playing without sound");
+                sound_output = SoundOutput::None;
+                0
             }
-            GameplayState::LoopPlayback => {
-                // If at the beginning of the loop, reset the memory
-                if looping_state.input_index == 0 {
-                    println!("Loop reset..");
-
-                    // Restore the snapshot memory back to the start of the snapshot
-                    unsafe {
-                        std::ptr::copy_nonoverlapping(
-                            looping_state.memory.as_ptr(),
-                            MEMORY_BASE_ADDR as *mut u8,
-                            MEMORY_LENGTH,
-                        );
+        };
+
+        // Silence is queued while the simulation is paused
+        sound_samples.clear();
+        sound_samples.resize(sound_frames * SOUND_CHANNELS, 0);
+
+        // The simulation is paused while the game library has failed
+        if failed_library.is_none() {
+            match &mut looping {
+                GameplayState::LoopRecording(recording) => {
+                    recording.push_input(LoopFrame {
+                        dt_seconds,
+                        buttons,
+                    });
+                }
+                GameplayState::LoopPlayback(playback) => {
+                    // If at the beginning of the loop, reset the memory and game state back
+                    // to the start of the snapshot
+                    if playback.input_index == 0 {
+                        println!("Loop reset..");
+                        state = playback.restore(&mut memory);
                     }
 
-                    // Reset the game state
-                    state = looping_state.game_state.clone();
+                    // Replay the recorded frame time so the same steps are simulated
+                    let input = playback.next_input();
+                    buttons = input.buttons;
+                    dt_seconds = input.dt_seconds;
                 }
+                GameplayState::Normal => {
+                    // Nothing to do, game play as normal
+                }
+            }
 
-                buttons = looping_state.next_input();
+            // Loop files only record the keyboard, so the gamepads are ignored while one
+            // is playing back
+            let mut game_controllers = match looping {
+                GameplayState::LoopPlayback(_) => [Controller::default(); MAX_CONTROLLERS],
+                _ => controllers,
+            };
+
+            // The keyboard is always connected
+            game_controllers[KEYBOARD_CONTROLLER] = Controller {
+                is_connected: true,
+                buttons,
+                ..Controller::default()
+            };
+
+            // Prepare the game state for the game logic
+            let mut game = Game {
+                framebuffer: window.framebuffer_mut(),
+                width: GAME_WINDOW_WIDTH,
+                height: GAME_WINDOW_HEIGHT,
+                dt_seconds,
+                error: Ok(()),
+                mouse,
+                controllers: game_controllers,
+                memory: &mut memory,
+                profiler,
+                background: &background,
+                player_assets,
+                sound_assets,
+            };
+
+            // Call the event code
+            {
+                timed_block!("game_update_and_render");
+                game_update_and_render(&mut game, &mut state);
             }
-            GameplayState::Normal => {
-                // Nothing to do, game play as normal
+
+            // Get the sound for this frame
+            if game.error.is_ok() && !sound_samples.is_empty() {
+                timed_block!("game_get_sound_samples");
+                let mut sound = SoundBuffer {
+                    samples_per_second: SAMPLES_PER_SECOND,
+                    samples: &mut sound_samples,
+                };
+
+                (game_code.game_get_sound_samples)(&mut game, &mut state, &mut sound);
+            }
+
+            // Pause the simulation on an error rather than running a broken library
+            if let Err(err) = game.error {
+                println!("Game error: {err}");
+                println!("Simulation paused until the game library is rebuilt");
+                failed_library = Some(game_code.generation);
+                sound_samples.fill(0);
             }
         }
 
-        // Prepare the game state for the game logic
-        let mut game = Game {
-            framebuffer: &mut window.framebuffer,
-            width: GAME_WINDOW_WIDTH,
-            height: GAME_WINDOW_HEIGHT,
-            error: Ok(()),
-            buttons: &buttons,
-            memory: &mut memory,
-            background: &background,
-            player_assets,
-        };
+        {
+            timed_block!("sound_output");
+            if let Err(err) = sound_output.write(&sound_samples) {
+                println!("Sound: {err}, playing without sound");
+                sound_output = SoundOutput::None;
+            }
+        }
 
-        // Call the event code
-        game_update_and_render(&mut game, &mut state);
+        // Wheel notches are reported to the game once
+        mouse.wheel = 0;
 
-        if let Err(e) = game.error {
-            println!("ERR: {:?}", e);
-            panic!();
+        // Place the updated framebuffer into the X11 window
+        {
+            timed_block!("put_image");
+            window.put_image();
         }
 
-        // Place the updated framebuffer into the X11 window
-        window.put_image();
+        // Pad the frame out to the target frame time
+        wait_for_frame_end(frame_start, frame_time);
 
-        // Get the time it took to execute this frame
-        let elapsed = frame_start.elapsed().as_millis();
+        // Collect the frame before the game library can be reloaded, since the records
+        // borrow the names of its blocks
+        drop(frame_block);
+        match &mut session {
+            Some(session) => session.collect(profiler),
+            None => profiler.clear(),
+        }
+    }
 
-        // Get the number of milliseconds remaining to hit the target frame count,
-        // clamping the value to zero
-        let remaining = (MILLISECONDS_PER_FRAME as u128).saturating_sub(elapsed);
+    // Write out the profile
+    if let (Some(session), Some(path)) = (&session, &options.profile) {
+        match session.save(path) {
+            Ok(()) => println!("Profile: wrote {}", path.display()),
+            Err(err) => println!("Profile: failed to write {}: {err}", path.display()),
+        }
+
+        if session.dropped() > 0 {
+            println!("Profile: dropped {} timed blocks", session.dropped());
+        }
+    }
+
+    // Complete the WAV file
+    if let Err(err) = sound_output.finish() {
+        println!("Sound: {err}");
+    }
 
-        // If there is any remaining time needed to pad until the next frame, sleep for
-        // that duration
-        if remaining > 0 {
-            std::thread::sleep(std::time::Duration::from_millis(
-                remaining.try_into().unwrap(),
-            ));
+    // Keep a recording that was still in progress when the window was closed
+    if let GameplayState::LoopRecording(recording) = looping {
+        match recording.save() {
+            Ok(()) => println!("Loop: saved slot {}", recording.slot),
+            Err(err) => println!("Loop: {err}"),
         }
     }
 }
@@ -0,0 +1,352 @@
+//! Sound output played through ALSA or written into a WAV file
+//!
+//! ALSA is loaded with `dlopen` when the window opens, so the platform still runs
+//! (silently) on machines without `libasound.so.2` or a sound card. The game is asked for
+//! enough samples each frame to keep [`LATENCY_FRAMES`] frames of sound queued ahead of
+//! the speakers. The WAV file sink takes exactly one frame of samples per frame.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::path::{Path, PathBuf};
+
+use game_state::{SOUND_CHANNELS, TARGET_FRAMES_PER_SECOND};
+
+use crate::dl::{self, Handle, Symbol};
+use crate::wav;
+
+/// The ALSA library loaded at runtime
+const LIBASOUND: &str = "libasound.so.2";
+
+/// The ALSA device sound is played through
+const DEVICE: &str = "default";
+
+/// Number of frames of sound kept queued ahead of the speakers. Higher values survive
+/// longer frame hitches at the cost of sound lagging behind the game.
+const LATENCY_FRAMES: usize = 3;
+
+/// Size of the ALSA buffer in microseconds, large enough to hold the queued frames
+const BUFFER_LATENCY_US: c_uint = 200_000;
+
+// Constants from `alsa/pcm.h`
+const SND_PCM_STREAM_PLAYBACK: c_int = 0;
+const SND_PCM_NONBLOCK: c_int = 1;
+const SND_PCM_FORMAT_S16_LE: c_int = 2;
+const SND_PCM_ACCESS_RW_INTERLEAVED: c_int = 3;
+const SND_PCM_STATE_PREPARED: c_int = 2;
+
+/// `EAGAIN` from `errno.h`, returned by a non-blocking write to a full buffer
+const EAGAIN: c_int = 11;
+
+/// Errors that can occur while outputting sound
+#[derive(Debug)]
+pub enum Error {
+    /// `dlopen` failed for the ALSA library
+    OpenLibrary(CString),
+
+    /// A required function was not found in the ALSA library
+    MissingSymbol(&'static str, CString),
+
+    /// An ALSA call failed
+    Alsa {
+        /// The failed function
+        call: &'static str,
+
+        /// The message from `snd_strerror`
+        message: String,
+    },
+
+    /// Failed to create or write the WAV file
+    Wav(PathBuf, std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::OpenLibrary(err) => {
+                write!(f, "failed to load {LIBASOUND}: {}", err.to_string_lossy())
+            }
+            Error::MissingSymbol(name, err) => {
+                write!(f, "missing ALSA function {name}: {}", err.to_string_lossy())
+            }
+            Error::Alsa { call, message } => write!(f, "{call} failed: {message}"),
+            Error::Wav(path, err) => write!(f, "failed to write {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Custom [`Result`] type for the sound output
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Functions loaded from the ALSA library
+struct AlsaFuncs {
+    snd_pcm_open:
+        Symbol<unsafe extern "C" fn(*mut *mut c_void, *const c_char, c_int, c_int) -> c_int>,
+    snd_pcm_set_params: Symbol<
+        unsafe extern "C" fn(*mut c_void, c_int, c_int, c_uint, c_uint, c_int, c_uint) -> c_int,
+    >,
+    snd_pcm_writei: Symbol<unsafe extern "C" fn(*mut c_void, *const c_void, c_ulong) -> c_long>,
+    snd_pcm_delay: Symbol<unsafe extern "C" fn(*mut c_void, *mut c_long) -> c_int>,
+    snd_pcm_avail_update: Symbol<unsafe extern "C" fn(*mut c_void) -> c_long>,
+    snd_pcm_recover: Symbol<unsafe extern "C" fn(*mut c_void, c_int, c_int) -> c_int>,
+    snd_pcm_state: Symbol<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    snd_pcm_start: Symbol<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    snd_pcm_close: Symbol<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    snd_strerror: Symbol<unsafe extern "C" fn(c_int) -> *const c_char>,
+}
+
+/// A playback stream opened on the default ALSA device
+pub struct Alsa {
+    /// Handle to the ALSA library, closed on drop
+    library: Handle,
+
+    /// The `snd_pcm_t` playback stream
+    pcm: *mut c_void,
+
+    /// Functions loaded from the ALSA library
+    funcs: AlsaFuncs,
+}
+
+impl Alsa {
+    /// Load ALSA and open a stream on the default device for interleaved 16 bit samples
+    /// played at `samples_per_second`
+    pub fn open(samples_per_second: u32) -> Result<Self> {
+        let name = CString::new(LIBASOUND).expect("CString failed for libasound");
+
+        // SAFETY: `name` is a valid C string
+        let library = unsafe { dl::dlopen(name.as_ptr(), dl::RTLD_LAZY) };
+        if library.0 == 0 {
+            // SAFETY: `dlerror` describes the failed `dlopen`
+            return Err(Error::OpenLibrary(unsafe {
+                CStr::from_ptr(dl::dlerror()).into()
+            }));
+        }
+
+        // Close the library if it is missing a function
+        macro_rules! load {
+            ($($name:ident),* $(,)?) => {
+                AlsaFuncs {
+                    $(
+                        $name: match dl::get_symbol(library, stringify!($name)) {
+                            Ok(symbol) => symbol,
+                            Err(err) => {
+                                // SAFETY: Nothing from the library is in use yet
+                                unsafe { dl::dlclose(library) };
+                                return Err(Error::MissingSymbol(stringify!($name), err));
+                            }
+                        },
+                    )*
+                }
+            };
+        }
+
+        let funcs = load!(
+            snd_pcm_open,
+            snd_pcm_set_params,
+            snd_pcm_writei,
+            snd_pcm_delay,
+            snd_pcm_avail_update,
+            snd_pcm_recover,
+            snd_pcm_state,
+            snd_pcm_start,
+            snd_pcm_close,
+            snd_strerror,
+        );
+
+        let mut alsa = Alsa {
+            library,
+            pcm: std::ptr::null_mut(),
+            funcs,
+        };
+
+        let device = CString::new(DEVICE).expect("CString failed for device");
+
+        // SAFETY: `pcm` is written by a successful open and the arguments are valid
+        unsafe {
+            let res = (alsa.funcs.snd_pcm_open)(
+                &mut alsa.pcm,
+                device.as_ptr(),
+                SND_PCM_STREAM_PLAYBACK,
+                SND_PCM_NONBLOCK,
+            );
+            alsa.check("snd_pcm_open", res)?;
+
+            #[allow(clippy::cast_possible_truncation)]
+            let res = (alsa.funcs.snd_pcm_set_params)(
+                alsa.pcm,
+                SND_PCM_FORMAT_S16_LE,
+                SND_PCM_ACCESS_RW_INTERLEAVED,
+                SOUND_CHANNELS as c_uint,
+                samples_per_second,
+                1,
+                BUFFER_LATENCY_US,
+            );
+            alsa.check("snd_pcm_set_params", res)?;
+        }
+
+        Ok(alsa)
+    }
+
+    /// Convert a negative ALSA return value into an [`Error`]
+    fn check(&self, call: &'static str, res: c_int) -> Result<()> {
+        if res >= 0 {
+            return Ok(());
+        }
+
+        // SAFETY: `snd_strerror` returns a static string for any error code
+        let message = unsafe { CStr::from_ptr((self.funcs.snd_strerror)(res)) };
+
+        Err(Error::Alsa {
+            call,
+            message: message.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// Restart the stream after `err`, such as an underrun after a long frame
+    fn recover(&mut self, call: &'static str, err: c_int) -> Result<()> {
+        // SAFETY: `pcm` is an open stream
+        let res = unsafe { (self.funcs.snd_pcm_recover)(self.pcm, err, 1) };
+        self.check(call, res)
+    }
+
+    /// Number of sample frames to write to bring the queue up to `target` sample frames
+    fn frames_needed(&mut self, target: usize) -> Result<usize> {
+        let mut queued: c_long = 0;
+
+        // SAFETY: `pcm` is an open stream
+        let res = unsafe { (self.funcs.snd_pcm_delay)(self.pcm, &mut queued) };
+        if res < 0 {
+            // Everything queued was lost to the underrun
+            self.recover("snd_pcm_delay", res)?;
+            queued = 0;
+        }
+
+        // SAFETY: `pcm` is an open stream
+        let mut available = unsafe { (self.funcs.snd_pcm_avail_update)(self.pcm) };
+        if available < 0 {
+            #[allow(clippy::cast_possible_truncation)]
+            self.recover("snd_pcm_avail_update", available as c_int)?;
+
+            // SAFETY: `pcm` is an open stream
+            available = unsafe { (self.funcs.snd_pcm_avail_update)(self.pcm) }.max(0);
+        }
+
+        let queued = usize::try_from(queued).unwrap_or(0);
+        let available = usize::try_from(available).unwrap_or(0);
+
+        Ok(target.saturating_sub(queued).min(available))
+    }
+
+    /// Queue the interleaved `samples` and start the stream if it is stopped
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        let mut remaining = samples;
+
+        while !remaining.is_empty() {
+            let frames = remaining.len() / SOUND_CHANNELS;
+
+            // SAFETY: `remaining` holds `frames` interleaved sample frames
+            let written = unsafe {
+                (self.funcs.snd_pcm_writei)(self.pcm, remaining.as_ptr().cast(), frames as c_ulong)
+            };
+
+            match usize::try_from(written) {
+                Ok(written) => remaining = &remaining[written * SOUND_CHANNELS..],
+
+                // The buffer is full, drop the rest rather than blocking the frame
+                #[allow(clippy::cast_possible_truncation)]
+                Err(_) if written as c_int == -EAGAIN => break,
+
+                #[allow(clippy::cast_possible_truncation)]
+                Err(_) => self.recover("snd_pcm_writei", written as c_int)?,
+            }
+        }
+
+        // A stream only starts itself once its buffer is full, which the queue never is
+        // SAFETY: `pcm` is an open stream
+        unsafe {
+            if (self.funcs.snd_pcm_state)(self.pcm) == SND_PCM_STATE_PREPARED {
+                let res = (self.funcs.snd_pcm_start)(self.pcm);
+                self.check("snd_pcm_start", res)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Alsa {
+    fn drop(&mut self) {
+        // SAFETY: The stream and library are no longer used
+        unsafe {
+            if !self.pcm.is_null() {
+                (self.funcs.snd_pcm_close)(self.pcm);
+            }
+
+            dl::dlclose(self.library);
+        }
+    }
+}
+
+/// Where the game sound goes
+pub enum SoundOutput {
+    /// Played through ALSA
+    Alsa(Alsa),
+
+    /// Written into a WAV file
+    Wav(wav::Writer, PathBuf),
+
+    /// Sound is disabled
+    None,
+}
+
+impl SoundOutput {
+    /// Play sound through ALSA, falling back to no sound if ALSA fails to open
+    pub fn alsa(samples_per_second: u32) -> Self {
+        match Alsa::open(samples_per_second) {
+            Ok(alsa) => SoundOutput::Alsa(alsa),
+            Err(err) => {
+                println!("Sound: {err}, playing without sound");
+                SoundOutput::None
+            }
+        }
+    }
+
+    /// Write sound played at `samples_per_second` into the WAV file at `path`
+    pub fn wav(path: &Path, samples_per_second: u32) -> Result<Self> {
+        let writer = wav::Writer::create(path, samples_per_second)
+            .map_err(|err| Error::Wav(path.to_path_buf(), err))?;
+
+        Ok(SoundOutput::Wav(writer, path.to_path_buf()))
+    }
+
+    /// Number of sample frames the game should produce for this frame of the game
+    /// running at `samples_per_second`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn frames_needed(&mut self, samples_per_second: u32) -> Result<usize> {
+        let frame_samples = (samples_per_second as f32 / TARGET_FRAMES_PER_SECOND) as usize;
+
+        match self {
+            SoundOutput::Alsa(alsa) => alsa.frames_needed(frame_samples * LATENCY_FRAMES),
+            SoundOutput::Wav(..) => Ok(frame_samples),
+            SoundOutput::None => Ok(0),
+        }
+    }
+
+    /// Output the interleaved `samples`
+    pub fn write(&mut self, samples: &[i16]) -> Result<()> {
+        match self {
+            SoundOutput::Alsa(alsa) => alsa.write(samples),
+            SoundOutput::Wav(writer, path) => writer
+                .write(samples)
+                .map_err(|err| Error::Wav(path.clone(), err)),
+            SoundOutput::None => Ok(()),
+        }
+    }
+
+    /// Finish the output, completing the WAV file
+    pub fn finish(self) -> Result<()> {
+        match self {
+            SoundOutput::Wav(writer, path) => writer.finish().map_err(|err| Error::Wav(path, err)),
+            SoundOutput::Alsa(_) | SoundOutput::None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Watches the game library for rebuilds using inotify
+//!
+//! The directory holding the library is watched rather than the library itself, since
+//! the linker and cargo replace the file instead of writing to the existing inode. The
+//! library is reported as rebuilt once it is safe to load:
+//!
+//! * The library was closed after being written or was moved into place
+//! * The library was created (cargo hard links the finished library into place) and
+//!   hasn't been modified for [`CREATE_SETTLE_TIME`]
+
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::raw::c_char;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+extern "C" {
+    fn inotify_init1(flags: i32) -> i32;
+    fn inotify_add_watch(fd: i32, pathname: *const c_char, mask: u32) -> i32;
+}
+
+/// File was modified
+const IN_MODIFY: u32 = 0x2;
+
+/// File opened for writing was closed
+const IN_CLOSE_WRITE: u32 = 0x8;
+
+/// File was moved into the watched directory
+const IN_MOVED_TO: u32 = 0x80;
+
+/// File was created in the watched directory
+const IN_CREATE: u32 = 0x100;
+
+/// The event queue overflowed and events were dropped
+const IN_Q_OVERFLOW: u32 = 0x4000;
+
+/// Reads from the inotify file return immediately if there are no events
+const IN_NONBLOCK: i32 = 0o4000;
+
+/// Close the inotify file on exec
+const IN_CLOEXEC: i32 = 0o2_000_000;
+
+/// Size of the fixed portion of an `inotify_event`
+const EVENT_HEADER_SIZE: usize = 16;
+
+/// Time a newly created library must go unmodified before it is considered complete
+const CREATE_SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Watches a library for being rebuilt
+pub struct LibraryWatcher {
+    /// The inotify instance
+    inotify: File,
+
+    /// Path of the watched library
+    path: PathBuf,
+
+    /// File name of the watched library in its directory
+    name: OsString,
+
+    /// When the library was created or last modified after being created, if it
+    /// hasn't been reported yet
+    created: Option<Instant>,
+}
+
+impl LibraryWatcher {
+    /// Watch the library at `path` for being rebuilt
+    pub fn new(path: &Path) -> std::io::Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| std::io::Error::other("library path has no file name"))?
+            .to_os_string();
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let dir_cstr = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|_| std::io::Error::other("library path contains a NUL byte"))?;
+
+        // SAFETY: `inotify_init1` returns a new file descriptor owned by this watcher
+        let inotify = unsafe {
+            let fd = inotify_init1(IN_NONBLOCK | IN_CLOEXEC);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            File::from_raw_fd(fd)
+        };
+
+        let mask = IN_MODIFY | IN_CLOSE_WRITE | IN_MOVED_TO | IN_CREATE;
+
+        // SAFETY: The inotify file descriptor is valid and the path is NUL terminated
+        let wd = unsafe { inotify_add_watch(inotify.as_raw_fd(), dir_cstr.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            inotify,
+            path: path.to_path_buf(),
+            name,
+            created: None,
+        })
+    }
+
+    /// Get the path of the watched library
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true once the library has been rebuilt since the last time it was
+    /// reported as rebuilt
+    pub fn poll(&mut self) -> bool {
+        let mut rebuilt = false;
+        let mut buffer = [0u8; 4096];
+
+        // Drain every pending event
+        while let Ok(len) = self.inotify.read(&mut buffer) {
+            let mut events = &buffer[..len];
+
+            while events.len() >= EVENT_HEADER_SIZE {
+                let mask = u32::from_ne_bytes(events[4..8].try_into().unwrap());
+                let name_len = u32::from_ne_bytes(events[12..16].try_into().unwrap()) as usize;
+                let name_end = (EVENT_HEADER_SIZE + name_len).min(events.len());
+
+                // The name is padded with NUL bytes
+                let name = &events[EVENT_HEADER_SIZE..name_end];
+                let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+                events = &events[name_end..];
+
+                // Events were lost, so assume the library changed
+                if mask & IN_Q_OVERFLOW != 0 {
+                    rebuilt = true;
+                    continue;
+                }
+
+                if name != self.name.as_bytes() {
+                    continue;
+                }
+
+                if mask & (IN_CLOSE_WRITE | IN_MOVED_TO) != 0 {
+                    // The library is complete
+                    rebuilt = true;
+                    self.created = None;
+                } else if mask & IN_CREATE != 0 || self.created.is_some() {
+                    // Wait for writes to the created library to settle
+                    self.created = Some(Instant::now());
+                }
+            }
+        }
+
+        // A created library that is no longer being written is complete
+        if self
+            .created
+            .is_some_and(|created| created.elapsed() >= CREATE_SETTLE_TIME)
+        {
+            rebuilt = true;
+            self.created = None;
+        }
+
+        rebuilt
+    }
+}
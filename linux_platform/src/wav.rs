@@ -0,0 +1,131 @@
+//! Minimal WAV writer for recording the game sound to disk
+//!
+//! Sound is written as 16 bit PCM with [`SOUND_CHANNELS`] interleaved channels. The sizes
+//! in the header are filled in once the writer is finished.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use game_state::SOUND_CHANNELS;
+
+/// Size of the RIFF header, `fmt ` chunk, and `data` chunk header
+const HEADER_SIZE: u32 = 44;
+
+/// `WAVE_FORMAT_PCM` format tag
+const WAVE_FORMAT_PCM: u16 = 1;
+
+/// Bytes in each sample of a single channel
+const BYTES_PER_SAMPLE: u16 = 2;
+
+/// Writes interleaved `i16` samples into a WAV file as they are produced
+pub struct Writer {
+    /// The file being written
+    file: BufWriter<File>,
+
+    /// Number of bytes of samples written
+    data_size: u32,
+}
+
+impl Writer {
+    /// Create a WAV file at `path` for samples played at `samples_per_second`
+    pub fn create(path: &Path, samples_per_second: u32) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let channels = SOUND_CHANNELS as u16;
+        let block_align = channels * BYTES_PER_SAMPLE;
+
+        // RIFF header, with the size filled in by `finish`
+        file.write_all(b"RIFF")?;
+        file.write_all(&0_u32.to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+
+        // Format chunk
+        file.write_all(b"fmt ")?;
+        file.write_all(&16_u32.to_le_bytes())?;
+        file.write_all(&WAVE_FORMAT_PCM.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&samples_per_second.to_le_bytes())?;
+        file.write_all(&(samples_per_second * u32::from(block_align)).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+
+        // Data chunk, with the size filled in by `finish`
+        file.write_all(b"data")?;
+        file.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(Self { file, data_size: 0 })
+    }
+
+    /// Append the interleaved `samples`
+    pub fn write(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+
+        let size = u32::try_from(samples.len() * usize::from(BYTES_PER_SAMPLE))
+            .ok()
+            .and_then(|size| self.data_size.checked_add(size))
+            .filter(|size| size.checked_add(HEADER_SIZE).is_some())
+            .ok_or_else(|| std::io::Error::other("WAV file is larger than 4 GiB"))?;
+
+        self.data_size = size;
+        Ok(())
+    }
+
+    /// Fill in the sizes in the header and flush the file
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+
+        self.file
+            .seek(SeekFrom::Start(u64::from(HEADER_SIZE) - 4))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game_state::SoundAsset;
+
+    #[test]
+    fn written_file_parses() {
+        let path = std::env::temp_dir().join(format!("wav_writer_{}.wav", std::process::id()));
+
+        // Three frames of interleaved left and right samples
+        let frames: [[i16; SOUND_CHANNELS]; 3] = [[0, -1], [16384, -16384], [i16::MAX, i16::MIN]];
+
+        let mut writer = Writer::create(&path, 48_000).unwrap();
+        writer.write(frames[0].as_slice()).unwrap();
+        writer.write(frames[1..].as_flattened()).unwrap();
+        writer.finish().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let data_size = frames.len() * SOUND_CHANNELS * usize::from(BYTES_PER_SAMPLE);
+
+        assert_eq!(data.len(), HEADER_SIZE as usize + data_size);
+        assert_eq!(read_u32(4) as usize, data.len() - 8);
+        assert_eq!(read_u32(HEADER_SIZE as usize - 4) as usize, data_size);
+
+        let asset = SoundAsset::from_data(&data).unwrap();
+        assert_eq!(asset.samples_per_second, 48_000);
+        assert_eq!(usize::from(asset.channels), SOUND_CHANNELS);
+        assert_eq!(asset.bits_per_sample, 16);
+        assert_eq!(asset.frame_count(), frames.len());
+
+        for (index, frame) in frames.iter().enumerate() {
+            for (channel, sample) in frame.iter().enumerate() {
+                assert_eq!(asset.sample(index, channel), f32::from(*sample) / 32768.);
+            }
+        }
+    }
+}
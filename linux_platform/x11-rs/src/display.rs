@@ -13,6 +13,7 @@ pub type VisualID = ::std::os::raw::c_ulong;
 pub type Colormap = ::std::os::raw::c_ulong;
 pub type Window   = ::std::os::raw::c_ulong;
 pub type Drawable = ::std::os::raw::c_ulong;
+pub type Atom     = ::std::os::raw::c_ulong;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
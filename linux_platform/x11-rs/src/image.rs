@@ -0,0 +1,258 @@
+//! Pixel buffers presented to a window, shared with the X server through MIT-SHM when
+//! the extension is available
+
+use std::os::raw::{c_char, c_int, c_ulong};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{Display, Visual, Window, XImage, GC, ZPIXMAP};
+use crate::{XCreateImage, XPutImage, XSync};
+
+#[link(name = "X11")]
+extern "system" {
+    fn XSetErrorHandler(handler: Option<ErrorHandler>) -> Option<ErrorHandler>;
+}
+
+#[link(name = "Xext")]
+extern "system" {
+    fn XShmQueryExtension(display: *mut Display) -> i32;
+    fn XShmCreateImage(display: *mut Display, visual: *mut Visual, depth: u32,
+        format: i32, data: *mut c_char, shminfo: *mut XShmSegmentInfo, width: u32,
+        height: u32) -> *mut XImage;
+    fn XShmAttach(display: *mut Display, shminfo: *mut XShmSegmentInfo) -> i32;
+    fn XShmDetach(display: *mut Display, shminfo: *mut XShmSegmentInfo) -> i32;
+    fn XShmPutImage(display: *mut Display, d: Window, gc: GC, image: *mut XImage,
+        src_x: i32, src_y: i32, dest_x: i32, dest_y: i32, width: u32, height: u32,
+        send_event: i32) -> i32;
+}
+
+extern "C" {
+    fn shmget(key: c_int, size: usize, shmflg: c_int) -> c_int;
+    fn shmat(shmid: c_int, shmaddr: *const u8, shmflg: c_int) -> *mut u8;
+    fn shmdt(shmaddr: *const u8) -> c_int;
+    fn shmctl(shmid: c_int, cmd: c_int, buf: *mut u8) -> c_int;
+}
+
+/// Xlib error handler
+type ErrorHandler = unsafe extern "C" fn(*mut Display, *mut u8) -> c_int;
+
+// Flags from `sys/ipc.h`
+const IPC_PRIVATE: c_int = 0;
+const IPC_CREAT:   c_int = 0o1000;
+const IPC_RMID:    c_int = 0;
+
+/// Set by [`attach_error_handler`] if the X server refused to attach the segment
+static ATTACH_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Records the failure of `XShmAttach`, which the X server reports asynchronously, such
+/// as for a display on another machine
+unsafe extern "C" fn attach_error_handler(_display: *mut Display, _event: *mut u8) -> c_int {
+    ATTACH_FAILED.store(true, Ordering::SeqCst);
+    0
+}
+
+#[repr(C)]
+struct XShmSegmentInfo {
+    shmseg: c_ulong,
+    shmid: c_int,
+    shmaddr: *mut c_char,
+    read_only: c_int
+}
+
+/// Where the pixels of an [`ImageBuffer`] live
+enum Storage {
+    /// On the heap, copied over the X connection on each put
+    Heap(Vec<u32>),
+
+    /// In a shared memory segment attached by the X server
+    Shared(Box<XShmSegmentInfo>),
+}
+
+/// A `width` by `height` buffer of pixels with the image used to present it
+pub(crate) struct ImageBuffer {
+    display: *mut Display,
+    image:   *mut XImage,
+    width:   u32,
+    height:  u32,
+    storage: Storage,
+}
+
+impl ImageBuffer {
+    /// Create a zeroed buffer of `width` by `height` pixels, in shared memory if
+    /// `shared` is set and the X server supports it
+    pub(crate) fn new(display: *mut Display, visual: *mut Visual, depth: u32,
+            width: u32, height: u32, shared: bool) -> ImageBuffer {
+        let shared = shared.then(|| unsafe {
+            Self::new_shared(display, visual, depth, width, height)
+        });
+
+        match shared.flatten() {
+            Some(buffer) => buffer,
+            None => unsafe { Self::new_heap(display, visual, depth, width, height) }
+        }
+    }
+
+    /// Create a buffer on the heap
+    unsafe fn new_heap(display: *mut Display, visual: *mut Visual, depth: u32,
+            width: u32, height: u32) -> ImageBuffer {
+        let pixels = vec![0; width as usize * height as usize];
+
+        let image = XCreateImage(
+            display,
+            visual,
+            /* depth:         */ depth,
+            /* format:        */ ZPIXMAP,
+            /* offset:        */ 0,
+            /* data:          */ pixels.as_ptr(),
+            /* width:         */ width,
+            /* height:        */ height,
+            /* bitmap_pad:    */ 8,
+            /* bytes_per_line */ 0);
+
+        assert!(!image.is_null(), "XCreateImage failed");
+        assert_eq!((*image).bytes_per_line as u32, width * 4, "Unsupported visual");
+
+        ImageBuffer { display, image, width, height, storage: Storage::Heap(pixels) }
+    }
+
+    /// Create a buffer in shared memory, returning `None` if MIT-SHM is unavailable
+    unsafe fn new_shared(display: *mut Display, visual: *mut Visual, depth: u32,
+            width: u32, height: u32) -> Option<ImageBuffer> {
+        if XShmQueryExtension(display) == 0 {
+            return None;
+        }
+
+        let mut info = Box::new(XShmSegmentInfo {
+            shmseg:    0,
+            shmid:     -1,
+            shmaddr:   std::ptr::null_mut(),
+            read_only: 0,
+        });
+
+        let image = XShmCreateImage(display, visual, depth, ZPIXMAP,
+            std::ptr::null_mut(), &mut *info, width, height);
+
+        if image.is_null() {
+            return None;
+        }
+
+        // The image is only used for its layout until the segment is attached
+        let destroy = |image: *mut XImage| {
+            (*image).data = std::ptr::null_mut();
+            ((*image).f.destroy_image.unwrap())(image);
+        };
+
+        if (*image).bytes_per_line as u32 != width * 4 {
+            destroy(image);
+            return None;
+        }
+
+        let size = (*image).bytes_per_line as usize * height as usize;
+        info.shmid = shmget(IPC_PRIVATE, size, IPC_CREAT | 0o600);
+        if info.shmid < 0 {
+            destroy(image);
+            return None;
+        }
+
+        let addr = shmat(info.shmid, std::ptr::null(), 0);
+        if addr as isize == -1 {
+            shmctl(info.shmid, IPC_RMID, std::ptr::null_mut());
+            destroy(image);
+            return None;
+        }
+
+        info.shmaddr = addr.cast();
+        (*image).data = addr.cast();
+
+        // Catch the X server refusing the segment rather than exiting
+        ATTACH_FAILED.store(false, Ordering::SeqCst);
+        let old_handler = XSetErrorHandler(Some(attach_error_handler));
+        XShmAttach(display, &mut *info);
+        XSync(display, false);
+        XSetErrorHandler(old_handler);
+
+        // The segment is removed once both sides detach from it, so it can't leak if
+        // the process dies
+        shmctl(info.shmid, IPC_RMID, std::ptr::null_mut());
+
+        if ATTACH_FAILED.load(Ordering::SeqCst) {
+            shmdt(addr);
+            destroy(image);
+            return None;
+        }
+
+        Some(ImageBuffer { display, image, width, height, storage: Storage::Shared(info) })
+    }
+
+    /// Returns true if the pixels are shared with the X server
+    pub(crate) fn is_shared(&self) -> bool {
+        matches!(self.storage, Storage::Shared(_))
+    }
+
+    /// Get the pixels of the buffer
+    pub(crate) fn pixels(&self) -> &[u32] {
+        match &self.storage {
+            Storage::Heap(pixels) => pixels,
+
+            // SAFETY: The segment holds `width * height` pixels while it is attached
+            Storage::Shared(info) => unsafe {
+                std::slice::from_raw_parts(info.shmaddr.cast::<u32>(), self.len())
+            }
+        }
+    }
+
+    /// Get the pixels of the buffer
+    pub(crate) fn pixels_mut(&mut self) -> &mut [u32] {
+        let len = self.len();
+
+        match &mut self.storage {
+            Storage::Heap(pixels) => pixels,
+
+            // SAFETY: The segment holds `width * height` pixels while it is attached
+            Storage::Shared(info) => unsafe {
+                std::slice::from_raw_parts_mut(info.shmaddr.cast::<u32>(), len)
+            }
+        }
+    }
+
+    /// Number of pixels in the buffer
+    fn len(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// Draw the whole buffer at the origin of `window` and wait until the X server has
+    /// finished reading it
+    pub(crate) fn put(&mut self, window: Window, gc: GC) {
+        unsafe {
+            match self.storage {
+                Storage::Shared(_) => {
+                    let result = XShmPutImage(self.display, window, gc, self.image, 
+                        0, 0, 0, 0, self.width, self.height, 0);
+                    assert!(result != 0, "XShmPutImage failed");
+                }
+                Storage::Heap(_) => {
+                    let result = XPutImage(self.display, window, gc, self.image, 
+                        0, 0, 0, 0, self.width, self.height);
+                    assert_eq!(result, 0);
+                }
+            }
+
+            XSync(self.display, false);
+        }
+    }
+}
+
+impl Drop for ImageBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            if let Storage::Shared(info) = &mut self.storage {
+                XShmDetach(self.display, &mut **info);
+                XSync(self.display, false);
+                shmdt(info.shmaddr.cast());
+            }
+
+            // The pixels are owned by the storage rather than the image
+            (*self.image).data = std::ptr::null_mut();
+            ((*self.image).f.destroy_image.unwrap())(self.image);
+        }
+    }
+}
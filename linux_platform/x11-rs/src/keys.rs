@@ -0,0 +1,228 @@
+//! Keyboard keys translated from X11 keysyms
+
+#![allow(clippy::missing_docs_in_private_items)]
+
+/// X11 keysym identifying the symbol on a key in the current keyboard layout
+pub type KeySym = u64;
+
+// Keysyms from `X11/keysymdef.h`
+const XK_SPACE:     KeySym = 0x0020;
+const XK_0:         KeySym = 0x0030;
+const XK_9:         KeySym = 0x0039;
+const XK_BACKSPACE: KeySym = 0xff08;
+const XK_TAB:       KeySym = 0xff09;
+const XK_RETURN:    KeySym = 0xff0d;
+const XK_ESCAPE:    KeySym = 0xff1b;
+const XK_HOME:      KeySym = 0xff50;
+const XK_LEFT:      KeySym = 0xff51;
+const XK_UP:        KeySym = 0xff52;
+const XK_RIGHT:     KeySym = 0xff53;
+const XK_DOWN:      KeySym = 0xff54;
+const XK_PAGE_UP:   KeySym = 0xff55;
+const XK_PAGE_DOWN: KeySym = 0xff56;
+const XK_END:       KeySym = 0xff57;
+const XK_INSERT:    KeySym = 0xff63;
+const XK_KP_ENTER:  KeySym = 0xff8d;
+const XK_KP_0:      KeySym = 0xffb0;
+const XK_KP_9:      KeySym = 0xffb9;
+const XK_F1:        KeySym = 0xffbe;
+const XK_F35:       KeySym = 0xffe0;
+const XK_SHIFT_L:   KeySym = 0xffe1;
+const XK_SHIFT_R:   KeySym = 0xffe2;
+const XK_CONTROL_L: KeySym = 0xffe3;
+const XK_CONTROL_R: KeySym = 0xffe4;
+const XK_CAPS_LOCK: KeySym = 0xffe5;
+const XK_META_L:    KeySym = 0xffe7;
+const XK_META_R:    KeySym = 0xffe8;
+const XK_ALT_L:     KeySym = 0xffe9;
+const XK_ALT_R:     KeySym = 0xffea;
+const XK_SUPER_L:   KeySym = 0xffeb;
+const XK_SUPER_R:   KeySym = 0xffec;
+const XK_DELETE:    KeySym = 0xffff;
+
+/// Keysyms at or above this value directly encode a Unicode code point
+const UNICODE_KEYSYM_BASE: KeySym = 0x0100_0000;
+
+// Modifier masks from the `state` of an X11 key event
+const SHIFT_MASK:   u32 = 1 << 0;
+const LOCK_MASK:    u32 = 1 << 1;
+const CONTROL_MASK: u32 = 1 << 2;
+const MOD1_MASK:    u32 = 1 << 3;
+const MOD4_MASK:    u32 = 1 << 6;
+
+/// Bits of the `state` of an X11 key event holding the active XKB group
+const GROUP_SHIFT: u32 = 13;
+const GROUP_MASK:  u32 = 0x3;
+
+/// Get the XKB group (the layout, when several are configured) active during a key event
+/// from its `state`
+pub fn keyboard_group(state: u32) -> i32 {
+    // Only two bits, so always fits
+    i32::try_from((state >> GROUP_SHIFT) & GROUP_MASK).unwrap()
+}
+
+/// A key on the keyboard, as labelled in the current keyboard layout
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A key producing a character, reported without Shift applied (`'a'` rather than
+    /// `'A'`, `'1'` rather than `'!'` on a US layout). Keys that only produce a digit
+    /// with Shift are reported as the digit (`'1'` rather than `'&'` on AZERTY layouts),
+    /// as are the keypad digits whether or not Num Lock is on.
+    Char(char),
+    Space,
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Up,
+    Down,
+    Left,
+    Right,
+    Shift,
+    Control,
+    Alt,
+    Super,
+    CapsLock,
+
+    /// Function key `F1` through `F35`
+    F(u8),
+
+    /// A key without a translation, holding its keysym
+    Unknown(KeySym),
+}
+
+impl Key {
+    /// Translate the `unshifted` and `shifted` keysyms of a key into a [`Key`]. The
+    /// unshifted keysym is used so a key is the same with or without Shift, except on
+    /// keys that need Shift for a digit, such as the number row of AZERTY layouts or the
+    /// keypad without Num Lock, so digit bindings work on every layout.
+    pub fn from_keysyms(unshifted: KeySym, shifted: KeySym) -> Key {
+        let is_digit = |keysym: KeySym| {
+            (XK_0..=XK_9).contains(&keysym) || (XK_KP_0..=XK_KP_9).contains(&keysym)
+        };
+
+        if is_digit(shifted) && !is_digit(unshifted) {
+            Key::from_keysym(shifted)
+        } else {
+            Key::from_keysym(unshifted)
+        }
+    }
+
+    /// Translate an X11 keysym into a [`Key`]
+    pub fn from_keysym(keysym: KeySym) -> Key {
+        match keysym {
+            XK_SPACE                                    => Key::Space,
+            XK_RETURN | XK_KP_ENTER                     => Key::Enter,
+            XK_ESCAPE                                   => Key::Escape,
+            XK_TAB                                      => Key::Tab,
+            XK_BACKSPACE                                => Key::Backspace,
+            XK_DELETE                                   => Key::Delete,
+            XK_INSERT                                   => Key::Insert,
+            XK_HOME                                     => Key::Home,
+            XK_END                                      => Key::End,
+            XK_PAGE_UP                                  => Key::PageUp,
+            XK_PAGE_DOWN                                => Key::PageDown,
+            XK_UP                                       => Key::Up,
+            XK_DOWN                                     => Key::Down,
+            XK_LEFT                                     => Key::Left,
+            XK_RIGHT                                    => Key::Right,
+            XK_SHIFT_L | XK_SHIFT_R                     => Key::Shift,
+            XK_CONTROL_L | XK_CONTROL_R                 => Key::Control,
+            XK_ALT_L | XK_ALT_R | XK_META_L | XK_META_R => Key::Alt,
+            XK_SUPER_L | XK_SUPER_R                     => Key::Super,
+            XK_CAPS_LOCK                                => Key::CapsLock,
+            XK_F1..=XK_F35                              => {
+                Key::F(u8::try_from(keysym - XK_F1 + 1).unwrap())
+            }
+
+            // Keypad digits are the same keys as the number row digits
+            XK_KP_0..=XK_KP_9 => {
+                Key::Char(char::from(b'0' + u8::try_from(keysym - XK_KP_0).unwrap()))
+            }
+
+            // Latin-1 keysyms are the same as their Unicode code points
+            0x21..=0x7e | 0xa0..=0xff => {
+                Key::Char(char::from(u8::try_from(keysym).unwrap()))
+            }
+
+            // Unicode keysyms used by most non-Latin layouts
+            UNICODE_KEYSYM_BASE.. => {
+                u32::try_from(keysym - UNICODE_KEYSYM_BASE)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map_or(Key::Unknown(keysym), Key::Char)
+            }
+
+            _ => Key::Unknown(keysym),
+        }
+    }
+}
+
+/// Modifier keys held during a key event
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Modifiers {
+    pub shift:     bool,
+    pub control:   bool,
+    pub alt:       bool,
+    pub super_key: bool,
+    pub caps_lock: bool,
+}
+
+impl Modifiers {
+    /// Get the modifiers from the `state` of an X11 key event
+    pub fn from_state(state: u32) -> Modifiers {
+        Modifiers {
+            shift:     state & SHIFT_MASK != 0,
+            control:   state & CONTROL_MASK != 0,
+            alt:       state & MOD1_MASK != 0,
+            super_key: state & MOD4_MASK != 0,
+            caps_lock: state & LOCK_MASK != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digits_on_every_layout() {
+        // US number row: `1` and `!`
+        assert_eq!(Key::from_keysyms(0x31, 0x21), Key::Char('1'));
+
+        // AZERTY number row: `&` and `1`, `é` and `2`
+        assert_eq!(Key::from_keysyms(0x26, 0x31), Key::Char('1'));
+        assert_eq!(Key::from_keysyms(0xe9, 0x32), Key::Char('2'));
+
+        // Letters and other keys are reported unshifted
+        assert_eq!(Key::from_keysyms(0x61, 0x41),           Key::Char('a'));
+        assert_eq!(Key::from_keysyms(XK_RETURN, XK_RETURN), Key::Enter);
+
+        // Keypad `End` and `1` without Num Lock, and `0` on its own
+        assert_eq!(Key::from_keysyms(0xff9c, 0xffb1), Key::Char('1'));
+        assert_eq!(Key::from_keysyms(0xff9e, XK_KP_0), Key::Char('0'));
+        assert_eq!(Key::from_keysym(XK_KP_9),         Key::Char('9'));
+    }
+
+    #[test]
+    fn groups_from_state() {
+        // Shift held in the first group
+        assert_eq!(keyboard_group(SHIFT_MASK), 0);
+
+        // Second group, such as Russian after switching from a US layout, with and
+        // without modifiers
+        assert_eq!(keyboard_group(1 << GROUP_SHIFT), 1);
+        assert_eq!(keyboard_group(1 << GROUP_SHIFT | SHIFT_MASK | CONTROL_MASK), 1);
+        assert_eq!(keyboard_group(3 << GROUP_SHIFT), 3);
+
+        // Pointer buttons above the group aren't part of it
+        assert_eq!(keyboard_group(1 << 15 | 2 << GROUP_SHIFT), 2);
+    }
+}
@@ -6,7 +6,21 @@
 #![allow(missing_docs)]
 
 mod display;
-pub use display::{Display, Screen, Visual, Window, XImage, Drawable, GC};
+pub use display::{Atom, Display, Screen, Visual, Window, XImage, Drawable, GC};
+
+mod keys;
+pub use keys::{Key, KeySym, Modifiers};
+use keys::keyboard_group;
+
+mod mouse;
+pub use mouse::{MouseButton, Wheel};
+use mouse::Pointer;
+
+mod scale;
+pub use scale::{Scaling, Viewport};
+
+mod image;
+use image::ImageBuffer;
  
 /// Errors for the linux platform
 #[derive(Debug)]
@@ -18,6 +32,9 @@ pub enum Error {
 /// Wrapper `Result` type for the linux platform
 pub type Result<T> = core::result::Result<T, Error>;
 
+use std::ffi::CString;
+use std::os::raw::c_char;
+
 #[link(name = "X11")]
 extern "system" {
     fn XOpenDisplay(display_name: *const u8) -> DisplayPtr;
@@ -38,9 +55,28 @@ extern "system" {
     fn XCheckWindowEvent(display: *mut Display, window: Window, mask: i64, 
         found_event: *mut XEvent) -> bool;
     fn XFlush(display: *mut Display) -> u32;
+    fn XEventsQueued(display: *mut Display, mode: i32) -> i32;
+    fn XPeekEvent(display: *mut Display, event: *mut XEvent) -> i32;
+    fn XkbSetDetectableAutoRepeat(display: *mut Display, detectable: i32, 
+        supported: *mut i32) -> i32;
+    fn XkbKeycodeToKeysym(display: *mut Display, keycode: u8, group: i32, level: i32)
+        -> KeySym;
+    fn XCheckTypedWindowEvent(display: *mut Display, window: Window, event_type: i32,
+        found_event: *mut XEvent) -> i32;
+    fn XStoreName(display: *mut Display, window: Window, name: *const c_char) -> i32;
+    fn XInternAtom(display: *mut Display, name: *const c_char, only_if_exists: i32) 
+        -> Atom;
+    fn XSetWMProtocols(display: *mut Display, window: Window, protocols: *mut Atom,
+        count: i32) -> i32;
+    fn XChangeProperty(display: *mut Display, window: Window, property: Atom, 
+        type_: Atom, format: i32, mode: i32, data: *const u8, nelements: i32) -> i32;
+    fn XSendEvent(display: *mut Display, window: Window, propagate: i32, mask: i64,
+        event: *mut XEvent) -> i32;
+    fn XDestroyWindow(display: *mut Display, window: Window) -> i32;
+    fn XCloseDisplay(display: *mut Display) -> i32;
 }
 
-#[repr(C)]
+#[repr(C, align(8))]
 pub struct XEvent {
     type_: i32,
     pad: [u8; 0x400]
@@ -90,13 +126,45 @@ pub enum EventMask {
 
 const EVENT_MASK: i64 = EventMask::Exposure as i64 
     | EventMask::KeyPress as i64
-    | EventMask::KeyRelease as i64;
+    | EventMask::KeyRelease as i64
+    | EventMask::ButtonPress as i64
+    | EventMask::ButtonRelease as i64
+    | EventMask::PointerMotion as i64
+    | EventMask::StructureNotify as i64
+    | EventMask::FocusChange as i64;
+
+// Events selected by `StructureNotify` that the window has no use for
+const DESTROY_NOTIFY:   i32 = 17;
+const UNMAP_NOTIFY:     i32 = 18;
+const MAP_NOTIFY:       i32 = 19;
+const REPARENT_NOTIFY:  i32 = 21;
+const GRAVITY_NOTIFY:   i32 = 24;
+const CIRCULATE_NOTIFY: i32 = 26;
 
 /// Event names. Used in "type" field in `XEvent` structures.
 #[derive(Copy, Clone, Debug)]
 pub enum Event {
-    KeyPress(char),
-    KeyRelease(char),
+    KeyPress(Key, Modifiers),
+    KeyRelease(Key, Modifiers),
+
+    /// The pointer moved to (`x`, `y`) in window coordinates
+    MouseMove { x: i32, y: i32 },
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+
+    /// The mouse wheel was turned by one notch
+    MouseWheel(Wheel),
+
+    /// The window was resized to `width` by `height` pixels. Also sent when the window
+    /// is moved without changing size.
+    Resize { width: u32, height: u32 },
+
+    /// The window manager asked to close the window
+    Close,
+
+    /// The window gained or lost the keyboard focus
+    FocusIn,
+    FocusOut,
     Expose,
     Unknown(i32)
 }
@@ -104,9 +172,16 @@ pub enum Event {
 impl From<i32> for Event {
     fn from(val: i32) -> Event {
         match val {
-            2 => Event::KeyPress('?'),
-            3 => Event::KeyRelease('?'),
+            2 => Event::KeyPress(Key::Unknown(0), Modifiers::default()),
+            3 => Event::KeyRelease(Key::Unknown(0), Modifiers::default()),
+            4 => Event::MouseDown(MouseButton::Other(0)),
+            5 => Event::MouseUp(MouseButton::Other(0)),
+            6 => Event::MouseMove { x: 0, y: 0 },
+            9 => Event::FocusIn,
+           10 => Event::FocusOut,
            12 => Event::Expose,
+           22 => Event::Resize { width: 0, height: 0 },
+           33 => Event::Close,
            _  => Event::Unknown(val)
         }
     }
@@ -115,10 +190,18 @@ impl From<i32> for Event {
 impl From<Event> for i32 {
     fn from(event: Event) -> i32 {
         match event {
-            Event::KeyPress(_)   => 2,
-            Event::KeyRelease(_) => 3,
-            Event::Expose        => 12,
-            Event::Unknown(val)  => val,
+            Event::KeyPress(..)   => 2,
+            Event::KeyRelease(..) => 3,
+            Event::MouseDown(_)   => 4,
+            Event::MouseWheel(_)  => 4,
+            Event::MouseUp(_)     => 5,
+            Event::MouseMove{..}  => 6,
+            Event::FocusIn        => 9,
+            Event::FocusOut       => 10,
+            Event::Expose         => 12,
+            Event::Resize{..}     => 22,
+            Event::Close          => 33,
+            Event::Unknown(val)   => val,
         }
     }
 }
@@ -127,6 +210,14 @@ impl From<Event> for i32 {
 #[repr(transparent)]
 pub struct DisplayPtr(*mut Display);
 
+impl Drop for DisplayPtr {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { XCloseDisplay(self.0); }
+        }
+    }
+}
+
 impl std::ops::Deref for DisplayPtr {
     type Target = *mut Display;
 
@@ -150,15 +241,15 @@ fn open_display() -> Option<DisplayPtr> {
 
 #[derive(Debug)]
 #[repr(C)]
-struct KeyEvent {
-    type_: u32,
+struct XKeyEvent {
+    type_: i32,
     serial: u64,
     send_event: i32,
     display: usize,
     window: Window,
     root: Window,
     subwindow: Window,
-    time: u32,
+    time: u64,
     x: i32,
     y: i32,
     x_root: i32,
@@ -168,15 +259,164 @@ struct KeyEvent {
     same_screen: i32
 }
 
+#[derive(Debug)]
+#[repr(C)]
+struct XButtonEvent {
+    type_: i32,
+    serial: u64,
+    send_event: i32,
+    display: usize,
+    window: Window,
+    root: Window,
+    subwindow: Window,
+    time: u64,
+    x: i32,
+    y: i32,
+    x_root: i32,
+    y_root: i32,
+    state: u32,
+    button: u32,
+    same_screen: i32
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct XMotionEvent {
+    type_: i32,
+    serial: u64,
+    send_event: i32,
+    display: usize,
+    window: Window,
+    root: Window,
+    subwindow: Window,
+    time: u64,
+    x: i32,
+    y: i32,
+    x_root: i32,
+    y_root: i32,
+    state: u32,
+    is_hint: u8,
+    same_screen: i32
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct XConfigureEvent {
+    type_: i32,
+    serial: u64,
+    send_event: i32,
+    display: usize,
+    event: Window,
+    window: Window,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    border_width: i32,
+    above: Window,
+    override_redirect: i32
+}
+
+#[derive(Debug)]
+#[repr(C)]
+struct XClientMessageEvent {
+    type_: i32,
+    serial: u64,
+    send_event: i32,
+    display: usize,
+    window: Window,
+    message_type: Atom,
+    format: i32,
+    data: [i64; 5]
+}
+
 const ZPIXMAP: i32 = 2;
 
+/// `NoSymbol`, the keysym of a key without a symbol at a given group and level
+const NO_SYMBOL: KeySym = 0;
+
+/// `QueuedAfterReading` for `XEventsQueued`
+const QUEUED_AFTER_READING: i32 = 1;
+
+/// `PropModeReplace` for `XChangeProperty`
+const PROP_MODE_REPLACE: i32 = 0;
+
+/// `_NET_WM_STATE` actions from the EWMH spec
+const NET_WM_STATE_REMOVE: i64 = 0;
+const NET_WM_STATE_ADD:    i64 = 1;
+
+/// EWMH source indication for requests from normal applications
+const SOURCE_APPLICATION: i64 = 1;
+
+/// Atoms used to talk to the window manager
+#[derive(Debug, Copy, Clone)]
+struct Atoms {
+    wm_protocols:             Atom,
+    wm_delete_window:         Atom,
+    net_wm_name:              Atom,
+    net_wm_state:             Atom,
+    net_wm_state_fullscreen:  Atom,
+    utf8_string:              Atom,
+}
+
+impl Atoms {
+    /// Get the atoms from the X server
+    unsafe fn intern(display: *mut Display) -> Atoms {
+        let intern = |name: &str| {
+            let name = CString::new(name).unwrap();
+            XInternAtom(display, name.as_ptr(), 0)
+        };
+
+        Atoms {
+            wm_protocols:            intern("WM_PROTOCOLS"),
+            wm_delete_window:        intern("WM_DELETE_WINDOW"),
+            net_wm_name:             intern("_NET_WM_NAME"),
+            net_wm_state:            intern("_NET_WM_STATE"),
+            net_wm_state_fullscreen: intern("_NET_WM_STATE_FULLSCREEN"),
+            utf8_string:             intern("UTF8_STRING"),
+        }
+    }
+}
+
 pub struct SimpleWindow {
-    pub display: DisplayPtr,
     pub window: Window,
-    pub framebuffer: Vec<u32>,
-    pub image: Option<XImage>,
+
+    /// Size of the framebuffer
     pub width: u32,
-    pub height: u32
+    pub height: u32,
+
+    /// Current size of the window
+    pub window_width: u32,
+    pub window_height: u32,
+
+    /// How the framebuffer is scaled into the window when their sizes differ
+    pub scaling: Scaling,
+
+    /// Area of the window the framebuffer is drawn into
+    pub viewport: Viewport,
+
+    /// Framebuffer drawn into by the game
+    framebuffer: ImageBuffer,
+
+    /// Window sized buffer holding the scaled framebuffer, used while the window size
+    /// differs from the framebuffer size
+    scaled: Option<ImageBuffer>,
+
+    /// Are new buffers shared with the X server through MIT-SHM when available
+    shared_memory: bool,
+
+    /// Does the X server report held keys as repeated presses without releases
+    detectable_auto_repeat: bool,
+
+    /// Is the window currently requested to be fullscreen
+    pub fullscreen: bool,
+
+    /// Atoms used to talk to the window manager
+    atoms: Atoms,
+
+    /// Connection to the X server. Kept last so it is closed after the buffers above
+    /// are released.
+    pub display: DisplayPtr,
 }
 
 impl SimpleWindow {
@@ -189,7 +429,10 @@ impl SimpleWindow {
             height:       None, 
             border_width: None,
             border:       None, 
-            background:   None
+            background:   None,
+            scaling:      None,
+            title:        None,
+            shared_memory: None
         }
     }
     
@@ -208,65 +451,207 @@ impl SimpleWindow {
     pub fn check_event(&self) -> Option<Event> {
         let mut event = XEvent::default();
 
-        let found = unsafe { 
-            XCheckWindowEvent(*self.display, self.window, EVENT_MASK, &mut event)
+        loop {
+            // Client messages can't be selected with an event mask, so check for them
+            // separately
+            if self.check_close(&mut event) {
+                return Some(Event::Close);
+            }
+
+            let found = unsafe { 
+                XCheckWindowEvent(*self.display, self.window, EVENT_MASK, &mut event)
+            };
+
+            // Return None and flush the display if there are no more events
+            if !found {
+                unsafe { XFlush(*self.display); } 
+                return None;
+            }
+
+            let res = match event.type_.into() {
+                res @ (Event::KeyPress(..) | Event::KeyRelease(..)) => {
+                    let key_event = (&mut event as *mut XEvent).cast::<XKeyEvent>();
+
+                    // Translate the key through the keysyms of the current keyboard
+                    // layout, from both shift levels so the number row of layouts
+                    // such as AZERTY still reports digits
+                    let (unshifted, shifted) = self.keysyms(unsafe { &*key_event });
+                    let key = Key::from_keysyms(unshifted, shifted);
+                    let modifiers = Modifiers::from_state(unsafe { (*key_event).state });
+
+                    match res {
+                        Event::KeyPress(..) => Event::KeyPress(key, modifiers),
+
+                        // Without detectable auto-repeat, a held key repeats as a
+                        // release immediately followed by a press with the same time.
+                        // Drop the release so the key stays held.
+                        _ if self.is_auto_repeat(unsafe { &*key_event }) => continue,
+                        _ => Event::KeyRelease(key, modifiers),
+                    }
+                }
+                res @ (Event::MouseDown(_) | Event::MouseUp(_)) => {
+                    let button_event = (&event as *const XEvent).cast::<XButtonEvent>();
+                    let button = unsafe { (*button_event).button };
+
+                    match (res, Pointer::from_button(button)) {
+                        (Event::MouseDown(_), Pointer::Button(button)) => {
+                            Event::MouseDown(button)
+                        }
+                        (_, Pointer::Button(button)) => Event::MouseUp(button),
+                        (Event::MouseDown(_), Pointer::Wheel(wheel)) => {
+                            Event::MouseWheel(wheel)
+                        }
+
+                        // Each wheel notch is reported on press, so skip its release
+                        (_, Pointer::Wheel(_)) => continue,
+                    }
+                }
+                Event::Resize { .. } => {
+                    let configure = (&event as *const XEvent).cast::<XConfigureEvent>();
+                    let (width, height) = unsafe { 
+                        ((*configure).width, (*configure).height) 
+                    };
+
+                    Event::Resize { 
+                        width:  u32::try_from(width).unwrap_or(1).max(1), 
+                        height: u32::try_from(height).unwrap_or(1).max(1),
+                    }
+                }
+                Event::MouseMove { .. } => {
+                    let motion_event = (&event as *const XEvent).cast::<XMotionEvent>();
+                    let (x, y) = unsafe { ((*motion_event).x, (*motion_event).y) };
+
+                    Event::MouseMove { x, y }
+                }
+
+                // Sent as the window is mapped, unmapped, or reparented by the window
+                // manager, such as when toggling fullscreen
+                Event::Unknown(
+                    DESTROY_NOTIFY | UNMAP_NOTIFY | MAP_NOTIFY | REPARENT_NOTIFY
+                    | GRAVITY_NOTIFY | CIRCULATE_NOTIFY
+                ) => continue,
+                res => res
+            };
+
+            return Some(res);
+        }
+    }
+
+    /// Get the unshifted and shifted keysyms of the key in `key_event` in the keyboard
+    /// group (layout) active when it was pressed. Keys with no symbols in that group,
+    /// such as keys only the first layout defines, use the first group.
+    fn keysyms(&self, key_event: &XKeyEvent) -> (KeySym, KeySym) {
+        // Core protocol keycodes are always from 8 to 255
+        let keycode = u8::try_from(key_event.keycode).unwrap_or(0);
+        let lookup  = |group: i32, level: i32| unsafe {
+            XkbKeycodeToKeysym(*self.display, keycode, group, level)
         };
 
-        // Return the event if found, otherwise return None and flush the display
-        if found {
-            let res = event.type_.into();
-            if matches!(res, Event::KeyPress(_) | Event::KeyRelease(_)) {
-                #[allow(clippy::cast_ptr_alignment)]
-                let key: &KeyEvent = unsafe {
-                    &*(event.pad.as_ptr().cast::<KeyEvent>())
-                };
-
-                let chr = match key.keycode {
-                    0x18 => 'q',
-                    0x19 => 'w',
-                    0x1a => 'e',
-                    0x1b => 'r',
-                    0x1c => 't',
-                    0x1d => 'y',
-                    0x1e => 'u',
-                    0x1f => 'i',
-                    0x20 => 'o',
-                    0x21 => 'p',
-                    0x26 => 'a',
-                    0x27 => 's',
-                    0x28 => 'd',
-                    0x29 => 'f',
-                    0x2a => 'g',
-                    0x2b => 'h',
-                    0x2c => 'j',
-                    0x2d => 'k',
-                    0x2e => 'l',
-                    0x34 => 'z',
-                    0x35 => 'x',
-                    0x36 => 'c',
-                    0x37 => 'v',
-                    0x38 => 'b',
-                    0x39 => 'n',
-                    0x3a => 'm',
-                    _ => '?'
-                };
-
-                let res = match res {
-                    Event::KeyPress(_)   => Event::KeyPress(chr),
-                    Event::KeyRelease(_) => Event::KeyRelease(chr),
-                    _ => unreachable!()
-                };
-
-                return Some(res);
+        let group = match keyboard_group(key_event.state) {
+            group if lookup(group, 0) != NO_SYMBOL => group,
+            _ => 0,
+        };
+
+        (lookup(group, 0), lookup(group, 1))
+    }
+
+    /// Returns true if the key `release` is part of an auto-repeat release/press pair
+    fn is_auto_repeat(&self, release: &XKeyEvent) -> bool {
+        if self.detectable_auto_repeat {
+            return false;
+        }
+
+        let mut next = XEvent::default();
+
+        unsafe {
+            if XEventsQueued(*self.display, QUEUED_AFTER_READING) == 0 {
+                return false;
             }
-            
-            Some(res)
-        } else {
-            unsafe { XFlush(*self.display); } 
-            None
+
+            XPeekEvent(*self.display, &mut next);
+        }
+
+        let next_key = (&next as *const XEvent).cast::<XKeyEvent>();
+        let next_key = unsafe { &*next_key };
+
+        matches!(next.type_.into(), Event::KeyPress(..))
+            && next_key.window  == release.window
+            && next_key.keycode == release.keycode
+            && next_key.time    == release.time
+    }
+
+    /// Returns true if the window manager sent a message asking to close the window
+    fn check_close(&self, event: &mut XEvent) -> bool {
+        const CLIENT_MESSAGE: i32 = 33;
+
+        let found = unsafe {
+            XCheckTypedWindowEvent(*self.display, self.window, CLIENT_MESSAGE, event) != 0
+        };
+
+        if !found {
+            return false;
+        }
+
+        let message = (event as *const XEvent).cast::<XClientMessageEvent>();
+        let (message_type, protocol) = unsafe { 
+            ((*message).message_type, (*message).data[0]) 
+        };
+
+        message_type == self.atoms.wm_protocols 
+            && Atom::try_from(protocol).ok() == Some(self.atoms.wm_delete_window)
+    }
+
+    /// Set the title of the window
+    pub fn set_title(&mut self, title: &str) {
+        // Titles with NUL bytes are cut off at the first NUL
+        let title = title.split('\0').next().unwrap_or_default();
+        let cstr  = CString::new(title).unwrap();
+        let len   = i32::try_from(title.len()).unwrap_or(i32::MAX);
+
+        unsafe {
+            // `WM_NAME` for older window managers and `_NET_WM_NAME` for UTF-8 titles
+            XStoreName(*self.display, self.window, cstr.as_ptr());
+            XChangeProperty(*self.display, self.window, self.atoms.net_wm_name, 
+                self.atoms.utf8_string, 8, PROP_MODE_REPLACE, title.as_ptr(), len);
         }
     }
 
+    /// Ask the window manager to make the window fullscreen or to restore it
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        let action = if fullscreen { NET_WM_STATE_ADD } else { NET_WM_STATE_REMOVE };
+
+        // Zero the whole event since `XSendEvent` reads all of it
+        let mut event = XEvent { type_: 0, pad: [0; 0x400] };
+        let message = (&mut event as *mut XEvent).cast::<XClientMessageEvent>();
+
+        unsafe {
+            (*message).type_        = 33;
+            (*message).window       = self.window;
+            (*message).message_type = self.atoms.net_wm_state;
+            (*message).format       = 32;
+            (*message).data         = [
+                action,
+                i64::try_from(self.atoms.net_wm_state_fullscreen).unwrap(),
+                0,
+                SOURCE_APPLICATION,
+                0
+            ];
+
+            // Requests to change the window state go to the root window
+            let mask = EventMask::SubstructureNotify as i64 
+                | EventMask::SubstructureRedirect as i64;
+            XSendEvent(*self.display, self.root_window(), 0, mask, &mut event);
+            XFlush(*self.display);
+        }
+
+        self.fullscreen = fullscreen;
+    }
+
+    /// Toggle the window between fullscreen and windowed
+    pub fn toggle_fullscreen(&mut self) {
+        self.set_fullscreen(!self.fullscreen);
+    }
+
     /// Get a reference to the display of the window
     pub fn display(&self) -> &Display {
         unsafe { &*(*self.display) }
@@ -314,42 +699,83 @@ impl SimpleWindow {
         screen.default_gc
     }
 
-    pub fn create_image(&mut self) {
-        self.image = Some(unsafe {
-            *XCreateImage(
-                *self.display, 
-                self.default_visual_mut(), 
-                /* depth:         */ self.default_depth(), 
-                /* format:        */ ZPIXMAP,
-                /* offset:        */ 0, 
-                /* data:          */ self.framebuffer.as_ptr(),
-                /* width:         */ self.width, 
-                /* height:        */ self.height, 
-                /* bitmap_pad:    */ 8, 
-                /* bytes_per_line */ 0)
-        });
+    /// Get the framebuffer pixels, `width` by `height` in size
+    pub fn framebuffer(&self) -> &[u32] {
+        self.framebuffer.pixels()
+    }
+
+    /// Get the framebuffer pixels, `width` by `height` in size
+    pub fn framebuffer_mut(&mut self) -> &mut [u32] {
+        self.framebuffer.pixels_mut()
+    }
+
+    /// Returns true if the framebuffer is presented through MIT-SHM rather than being
+    /// copied over the X connection
+    pub fn is_shared_memory(&self) -> bool {
+        self.framebuffer.is_shared()
+    }
+
+    /// Create a zeroed buffer of the given size for presenting to this window
+    fn new_buffer(&mut self, width: u32, height: u32) -> ImageBuffer {
+        let depth  = self.default_depth();
+        let visual = std::ptr::from_mut(self.default_visual_mut());
+        ImageBuffer::new(*self.display, visual, depth, width, height, self.shared_memory)
+    }
+
+    /// Handle the window being resized to `width` by `height`, recalculating where the
+    /// framebuffer is drawn
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.window_width, self.window_height) {
+            return;
+        }
+
+        self.window_width  = width;
+        self.window_height = height;
+        self.update_viewport();
+    }
+
+    /// Change how the framebuffer is scaled into the window
+    pub fn set_scaling(&mut self, scaling: Scaling) {
+        self.scaling = scaling;
+        self.update_viewport();
+    }
+
+    /// Recalculate the viewport for the current window size and scaling
+    fn update_viewport(&mut self) {
+        self.viewport = Viewport::fit((self.width, self.height), 
+            (self.window_width, self.window_height), self.scaling);
+
+        // Release the old buffer before creating one for the new size
+        self.scaled = None;
+
+        // Draw the framebuffer directly if it exactly fills the window
+        if (self.window_width, self.window_height) == (self.width, self.height) {
+            return;
+        }
+
+        // The new buffer starts black, so the bars outside of the viewport stay black
+        self.scaled = Some(self.new_buffer(self.window_width, self.window_height));
+    }
 
-        println!("Image: {:#x?}", self.image);
+    /// Map a position in the window to a position in the framebuffer. Positions in the
+    /// black bars around the framebuffer map outside of the framebuffer.
+    pub fn to_framebuffer(&self, x: i32, y: i32) -> (i32, i32) {
+        self.viewport.to_source((self.width, self.height), x, y)
     }
 
     pub fn put_image(&mut self) {
-        unsafe {
-            let result = XPutImage(
-                /* display: */ *self.display,
-                /* d:       */ self.window,
-                /* gc:      */ self.default_gc(), 
-                /* image:   */ &mut self.image.unwrap(),
-                /* src_x:   */ 0, 
-                /* src_y:   */ 0, 
-                /* dest_x:  */ 0, 
-                /* dest_y:  */ 0, 
-                /* width:   */ self.width, 
-                /* height:  */ self.height);
-
-            assert_eq!(result, 0);
-
-            XSync(*self.display, false);
-        };
+        let gc = self.default_gc();
+
+        // Scale the framebuffer into the window sized buffer if the sizes differ
+        match &mut self.scaled {
+            Some(scaled) => {
+                self.viewport.blit(self.framebuffer.pixels(), (self.width, self.height), 
+                    scaled.pixels_mut(), self.window_width);
+
+                scaled.put(self.window, gc);
+            }
+            None => self.framebuffer.put(self.window, gc),
+        }
     }
 }
 
@@ -362,7 +788,10 @@ pub struct SimpleWindowBuilder {
     height:       Option<u32>, 
     border_width: Option<u32>,
     border:       Option<u64>, 
-    background:   Option<u64>
+    background:   Option<u64>,
+    scaling:      Option<Scaling>,
+    title:        Option<String>,
+    shared_memory: Option<bool>
 }
 
 
@@ -402,6 +831,22 @@ impl SimpleWindowBuilder {
         self
     }
 
+    pub fn scaling(mut self, val: Scaling) -> Self {
+        self.scaling = Some(val);
+        self
+    }
+
+    pub fn title(mut self, val: &str) -> Self {
+        self.title = Some(val.to_string());
+        self
+    }
+
+    /// Present through MIT-SHM when the X server supports it (default: true)
+    pub fn shared_memory(mut self, val: bool) -> Self {
+        self.shared_memory = Some(val);
+        self
+    }
+
     /// Create the `SimpleWindow` from the given parameters
     pub fn finish(self) -> Result<SimpleWindow> {
         let display = open_display().expect("Failed to open x11 display");
@@ -413,7 +858,7 @@ impl SimpleWindowBuilder {
             let root_window  = screen.root;
 
             let width  = self.width.unwrap_or(600);
-            let height = self.width.unwrap_or(800);
+            let height = self.height.unwrap_or(800);
 
             let window = XCreateSimpleWindow(
                 *display, 
@@ -429,21 +874,55 @@ impl SimpleWindowBuilder {
 
             XSelectInput(*display, window, EVENT_MASK);
 
-            XMapWindow(*display, window);
+            // Ask the window manager to send a message rather than killing the
+            // connection when the window is closed
+            let atoms = Atoms::intern(*display);
+            let mut protocols = [atoms.wm_delete_window];
+            XSetWMProtocols(*display, window, protocols.as_mut_ptr(), 1);
 
-            // XkbSetAutoRepeatRate(*display, 0x100, 1iiiiiiiiiiiiiiiiiiiiii, 1);
+            // Report held keys as repeated presses rather than release/press pairs
+            let mut supported = 0;
+            XkbSetDetectableAutoRepeat(*display, 1, &mut supported);
+            let detectable_auto_repeat = supported != 0;
 
-            let num_bytes   = usize::try_from(width * height).unwrap();
-            let framebuffer = vec![0; num_bytes];
+            let shared_memory = self.shared_memory.unwrap_or(true);
+            let framebuffer = ImageBuffer::new(*display, screen.root_visual, 
+                u32::try_from(screen.root_depth).unwrap(), width, height, shared_memory);
 
-            Ok(SimpleWindow {
-                display,
+            let mut simple_window = SimpleWindow {
                 window,
-                framebuffer,
-                image: None,
                 width,
                 height,
-            })
+                window_width: width,
+                window_height: height,
+                scaling: self.scaling.unwrap_or_default(),
+                viewport: Viewport { x: 0, y: 0, width, height },
+                framebuffer,
+                scaled: None,
+                shared_memory,
+                detectable_auto_repeat,
+                fullscreen: false,
+                atoms,
+                display,
+            };
+
+            // Set the title before mapping so the window never shows up untitled
+            if let Some(title) = &self.title {
+                simple_window.set_title(title);
+            }
+
+            XMapWindow(*simple_window.display, window);
+
+            Ok(simple_window)
+        }
+    }
+}
+
+impl Drop for SimpleWindow {
+    fn drop(&mut self) {
+        // The buffers and then the display are released after this as the fields drop
+        unsafe {
+            XDestroyWindow(*self.display, self.window);
         }
     }
 }
@@ -0,0 +1,59 @@
+//! Mouse buttons translated from X11 pointer button numbers
+
+// Pointer button numbers from `X11/X.h` and the conventional wheel and side buttons
+const BUTTON_LEFT:        u32 = 1;
+const BUTTON_MIDDLE:      u32 = 2;
+const BUTTON_RIGHT:       u32 = 3;
+const BUTTON_WHEEL_UP:    u32 = 4;
+const BUTTON_WHEEL_DOWN:  u32 = 5;
+const BUTTON_WHEEL_LEFT:  u32 = 6;
+const BUTTON_WHEEL_RIGHT: u32 = 7;
+const BUTTON_BACK:        u32 = 8;
+const BUTTON_FORWARD:     u32 = 9;
+
+/// A button on the mouse
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Back,
+    Forward,
+
+    /// A button without a translation, holding its X11 button number
+    Other(u32),
+}
+
+/// A single notch of the mouse wheel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Wheel {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// What an X11 pointer button number reports
+pub(crate) enum Pointer {
+    Button(MouseButton),
+    Wheel(Wheel),
+}
+
+impl Pointer {
+    /// Translate an X11 pointer button number. X11 reports the wheel as buttons 4
+    /// through 7, pressed and released once per notch.
+    pub(crate) fn from_button(button: u32) -> Pointer {
+        match button {
+            BUTTON_LEFT        => Pointer::Button(MouseButton::Left),
+            BUTTON_MIDDLE      => Pointer::Button(MouseButton::Middle),
+            BUTTON_RIGHT       => Pointer::Button(MouseButton::Right),
+            BUTTON_BACK        => Pointer::Button(MouseButton::Back),
+            BUTTON_FORWARD     => Pointer::Button(MouseButton::Forward),
+            BUTTON_WHEEL_UP    => Pointer::Wheel(Wheel::Up),
+            BUTTON_WHEEL_DOWN  => Pointer::Wheel(Wheel::Down),
+            BUTTON_WHEEL_LEFT  => Pointer::Wheel(Wheel::Left),
+            BUTTON_WHEEL_RIGHT => Pointer::Wheel(Wheel::Right),
+            _                  => Pointer::Button(MouseButton::Other(button)),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Scaling the fixed size framebuffer into a resized window
+
+/// How the framebuffer is scaled to fit the window. The framebuffer keeps its aspect
+/// ratio and is centered with black bars filling the rest of the window.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scaling {
+    /// Stretch the framebuffer to the largest size that fits the window
+    #[default]
+    Stretch,
+
+    /// Scale the framebuffer by the largest whole number that fits the window, keeping
+    /// pixels square. Falls back to `Stretch` if the window is smaller than the
+    /// framebuffer.
+    Integer,
+}
+
+/// The area of the window the framebuffer is drawn into
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x:      u32,
+    pub y:      u32,
+    pub width:  u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    /// Get the centered viewport for a `source` sized framebuffer in a `window` sized
+    /// window using the given `scaling`
+    pub fn fit(source: (u32, u32), window: (u32, u32), scaling: Scaling) -> Viewport {
+        let (src_width, src_height) = (u64::from(source.0), u64::from(source.1));
+        let (win_width, win_height) = (u64::from(window.0), u64::from(window.1));
+
+        let integer_scale = (win_width / src_width).min(win_height / src_height);
+
+        let (width, height) = match scaling {
+            Scaling::Integer if integer_scale > 0 => {
+                (src_width * integer_scale, src_height * integer_scale)
+            }
+
+            // Whichever side of the window is relatively smaller limits the scale
+            _ if win_width * src_height <= win_height * src_width => {
+                (win_width, win_width * src_height / src_width)
+            }
+            _ => (win_height * src_width / src_height, win_height),
+        };
+
+        // The size never exceeds the window, so it fits back in a `u32`
+        let width  = u32::try_from(width.max(1)).unwrap();
+        let height = u32::try_from(height.max(1)).unwrap();
+
+        Viewport {
+            x: window.0.saturating_sub(width) / 2,
+            y: window.1.saturating_sub(height) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// Map a window position to a position in the `source` sized framebuffer. Positions
+    /// in the black bars map outside of the framebuffer.
+    pub fn to_source(&self, source: (u32, u32), x: i32, y: i32) -> (i32, i32) {
+        let map = |pos: i32, start: u32, len: u32, src_len: u32| {
+            let offset = i64::from(pos) - i64::from(start);
+            let mapped = (offset * i64::from(src_len)).div_euclid(i64::from(len));
+            i32::try_from(mapped).unwrap_or(if mapped < 0 { i32::MIN } else { i32::MAX })
+        };
+
+        (
+            map(x, self.x, self.width,  source.0),
+            map(y, self.y, self.height, source.1),
+        )
+    }
+
+    /// Scale the `source` framebuffer with nearest neighbor sampling into this viewport
+    /// of the `dest` buffer, which is `dest_width` pixels wide. The area outside of the
+    /// viewport is left untouched.
+    pub fn blit(&self, source: &[u32], source_size: (u32, u32), dest: &mut [u32],
+            dest_width: u32) {
+        let src_width  = source_size.0 as usize;
+        let src_height = source_size.1 as usize;
+        let width      = self.width as usize;
+        let height     = self.height as usize;
+
+        // Source column for each column of the viewport
+        let columns: Vec<usize> = (0..width).map(|x| x * src_width / width).collect();
+
+        for y in 0..height {
+            let src_row  = y * src_height / height;
+            let src      = &source[src_row * src_width..][..src_width];
+            let dest_row = (self.y as usize + y) * dest_width as usize + self.x as usize;
+            let dest     = &mut dest[dest_row..][..width];
+
+            for (pixel, column) in dest.iter_mut().zip(&columns) {
+                *pixel = src[*column];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Size of the framebuffer in the tests
+    const SOURCE: (u32, u32) = (960, 540);
+
+    /// Build a viewport at (`x`, `y`) sized `width` by `height`
+    fn viewport(x: u32, y: u32, width: u32, height: u32) -> Viewport {
+        Viewport { x, y, width, height }
+    }
+
+    #[test]
+    fn fit_same_size() {
+        for scaling in [Scaling::Stretch, Scaling::Integer] {
+            assert_eq!(Viewport::fit(SOURCE, SOURCE, scaling), viewport(0, 0, 960, 540));
+        }
+    }
+
+    #[test]
+    fn fit_integer_and_stretch() {
+        // Integer scaling only uses whole multiples, stretching fills the narrower side
+        assert_eq!(Viewport::fit(SOURCE, (2000, 1200), Scaling::Integer), viewport(40, 60, 1920, 1080));
+        assert_eq!(Viewport::fit(SOURCE, (2000, 1200), Scaling::Stretch), viewport(0,  37, 2000, 1125));
+
+        // A wide window has bars on the sides instead
+        assert_eq!(Viewport::fit(SOURCE, (3000, 1100), Scaling::Integer), viewport(540, 10, 1920, 1080));
+        assert_eq!(Viewport::fit(SOURCE, (3000, 1100), Scaling::Stretch), viewport(522, 0,  1955, 1100));
+    }
+
+    #[test]
+    fn fit_smaller_window() {
+        // Integer scaling falls back to stretching when the framebuffer doesn't fit once
+        for scaling in [Scaling::Stretch, Scaling::Integer] {
+            assert_eq!(Viewport::fit(SOURCE, (480, 400), scaling), viewport(0, 65, 480, 270));
+        }
+
+        // Even an empty window gets a pixel
+        assert_eq!(Viewport::fit(SOURCE, (0, 0), Scaling::Stretch), viewport(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn fit_odd_sizes() {
+        // An odd number of spare pixels leaves the extra one on the right and bottom
+        assert_eq!(Viewport::fit(SOURCE, (963, 545),  Scaling::Integer), viewport(1, 2,   960, 540));
+        assert_eq!(Viewport::fit(SOURCE, (961, 541),  Scaling::Stretch), viewport(0, 0,   961, 540));
+        assert_eq!(Viewport::fit(SOURCE, (961, 1001), Scaling::Stretch), viewport(0, 230, 961, 540));
+    }
+
+    #[test]
+    fn to_source_edges() {
+        let view = viewport(40, 60, 1920, 1080);
+
+        // Corners of the viewport map to the corners of the framebuffer
+        assert_eq!(view.to_source(SOURCE, 40,   60),   (0,   0));
+        assert_eq!(view.to_source(SOURCE, 1959, 1139), (959, 539));
+        assert_eq!(view.to_source(SOURCE, 1000, 600),  (480, 270));
+
+        // Clicks in the bars map just outside of the framebuffer
+        assert_eq!(view.to_source(SOURCE, 39,   59),   (-1,  -1));
+        assert_eq!(view.to_source(SOURCE, 0,    0),    (-20, -30));
+        assert_eq!(view.to_source(SOURCE, 1960, 1140), (960, 540));
+    }
+
+    #[test]
+    fn to_source_stretched() {
+        // Positions between framebuffer pixels round towards the top left
+        let view = viewport(0, 37, 2000, 1125);
+
+        assert_eq!(view.to_source(SOURCE, 0,    37),   (0,   0));
+        assert_eq!(view.to_source(SOURCE, 1999, 1161), (959, 539));
+        assert_eq!(view.to_source(SOURCE, 2000, 1162), (960, 540));
+        assert_eq!(view.to_source(SOURCE, 1,    36),   (0,   -1));
+    }
+
+    #[test]
+    fn to_source_saturates() {
+        // Far outside of a tiny viewport is clamped rather than overflowing
+        let view = viewport(0, 0, 1, 1);
+
+        assert_eq!(view.to_source(SOURCE, i32::MAX, i32::MIN), (i32::MAX, i32::MIN));
+    }
+}
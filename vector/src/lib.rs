@@ -153,8 +153,8 @@ impl From<Vector2<usize>> for Vector2<u16> {
 impl From<Vector2<u16>> for Vector2<f32> {
     fn from(other: Vector2<u16>) -> Vector2<f32> {
         Vector2 {
-            x: other.x.try_into().unwrap(),
-            y: other.y.try_into().unwrap(),
+            x: other.x.into(),
+            y: other.y.into(),
         }
     }
 }